@@ -0,0 +1,557 @@
+mod rpc;
+
+use anchor_lang::{AccountDeserialize, Discriminator};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use credix::state::{CredixPass, GlobalMarketState};
+use credix_client::{create_signing_authority, find_global_market_state, CredixMarket};
+use rpc::RpcClient;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use std::fs;
+use std::str::FromStr;
+
+fn main() {
+    if let Err(err) = run(&app().get_matches()) {
+        eprintln!("error: {:#}", err);
+        std::process::exit(1);
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let seed = Arg::with_name("seed")
+        .long("seed")
+        .takes_value(true)
+        .required(true)
+        .help("Seed of the global market state");
+    let wallets = [
+        Arg::with_name("wallet")
+            .required_unless("csv")
+            .help("Wallet of the pass holder"),
+        Arg::with_name("csv")
+            .long("csv")
+            .takes_value(true)
+            .conflicts_with("wallet")
+            .help("CSV file with a wallet in the first column of every line"),
+    ];
+    let duration = |help| {
+        Arg::with_name("duration")
+            .long("duration")
+            .takes_value(true)
+            .required(true)
+            .help(help)
+    };
+    let max_deposits = Arg::with_name("max")
+        .long("max")
+        .takes_value(true)
+        .required(true)
+        .help("Maximum of base tokens deposited, 0 disables the cap");
+
+    App::new("credix")
+        .about("Operates credix markets and the credix permissioned market")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .short("u")
+                .global(true)
+                .takes_value(true)
+                .default_value("http://127.0.0.1:8899")
+                .help("RPC URL of the cluster"),
+        )
+        .arg(
+            Arg::with_name("keypair")
+                .long("keypair")
+                .short("k")
+                .global(true)
+                .takes_value(true)
+                .help("Keypair paying for and signing the transactions [default: ~/.config/solana/id.json]"),
+        )
+        .arg(
+            Arg::with_name("program-id")
+                .long("program-id")
+                .global(true)
+                .takes_value(true)
+                .help("Address of the credix program"),
+        )
+        .subcommand(
+            SubCommand::with_name("init-market")
+                .about("Initializes a credix market")
+                .arg(seed.clone())
+                .arg(pubkey_arg("gatekeeper-network", "Civic gatekeeper network"))
+                .arg(pubkey_arg("base-mint", "Mint of the liquidity pool base token"))
+                .arg(
+                    Arg::with_name("lp-mint-keypair")
+                        .long("lp-mint-keypair")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Keypair of the LP token mint to create"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("create-pass")
+                .about("Creates credix passes")
+                .arg(seed.clone())
+                .args(&wallets),
+        )
+        .subcommand(
+            SubCommand::with_name("update-pass")
+                .about("Activates or deactivates credix passes")
+                .arg(seed.clone())
+                .args(&wallets)
+                .arg(
+                    Arg::with_name("active")
+                        .long("active")
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(&["true", "false"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("close-pass")
+                .about("Closes credix passes, the keypair must be the market owner")
+                .arg(seed.clone())
+                .args(&wallets),
+        )
+        .subcommand(
+            SubCommand::with_name("update-deposit-cap")
+                .about("Caps the deposits of the pool, the keypair must be the market owner")
+                .arg(seed.clone())
+                .arg(max_deposits.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("update-investor-cap")
+                .about("Caps the deposits of pass holders, the keypair must be the market owner")
+                .arg(seed.clone())
+                .args(&wallets)
+                .arg(max_deposits),
+        )
+        .subcommand(
+            SubCommand::with_name("update-withdraw-epochs")
+                .about("Sets the duration of withdrawal epochs, the keypair must be the market owner")
+                .arg(seed.clone())
+                .arg(duration("Duration of the epochs in seconds, 0 stops new requests")),
+        )
+        .subcommand(
+            SubCommand::with_name("update-lockup")
+                .about("Sets the lockup of LP tokens after deposits, the keypair must be the market owner")
+                .arg(seed.clone())
+                .arg(duration("Duration of the lockup in seconds, 0 disables it")),
+        )
+        .subcommand(
+            SubCommand::with_name("close-withdraw-epoch")
+                .about("Closes the current withdrawal epoch once over")
+                .arg(seed.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("fill-withdraw-request")
+                .about("Fills the withdrawal requests of pass holders in the last closed epoch")
+                .arg(seed.clone())
+                .args(&wallets),
+        )
+        .subcommand(
+            SubCommand::with_name("refund-withdraw-request")
+                .about("Returns the LP tokens of withdrawal requests of pass holders, the keypair must be the market owner")
+                .arg(seed.clone())
+                .args(&wallets),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate-market")
+                .about("Migrates the global market state to the current layout, the keypair must be the upgrade authority of the credix program")
+                .arg(seed.clone())
+                .arg(pubkey_arg(
+                    "owner",
+                    "Owner of the market, recorded on markets created before their owner was",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate-pass")
+                .about("Migrates credix passes to the current layout, the keypair must be the market owner")
+                .arg(seed.clone())
+                .args(&wallets),
+        )
+        .subcommand(
+            SubCommand::with_name("create-signing-pda")
+                .about("Creates the signing authority of the permissioned market")
+                .arg(
+                    Arg::with_name("proxy-program-id")
+                        .long("proxy-program-id")
+                        .takes_value(true)
+                        .help("Address of the credix permissioned market program"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("show-market")
+                .about("Prints the global market state")
+                .arg(seed.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("show-pass")
+                .about("Prints the credix pass of a wallet")
+                .arg(seed)
+                .arg(
+                    Arg::with_name("wallet")
+                        .required(true)
+                        .help("Wallet of the pass holder"),
+                ),
+        )
+}
+
+fn pubkey_arg(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .long(name)
+        .takes_value(true)
+        .required(true)
+        .help(help)
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let rpc = RpcClient::new(matches.value_of("url").unwrap());
+    let program_id = match matches.value_of("program-id") {
+        Some(program_id) => parse_pubkey(program_id)?,
+        None => credix::ID,
+    };
+
+    match matches.subcommand() {
+        ("init-market", Some(args)) => {
+            let payer = payer(matches)?;
+            let lp_token_mint = read_keypair(args.value_of("lp-mint-keypair").unwrap())?;
+            let market = CredixMarket::with_program_id(
+                program_id,
+                args.value_of("seed").unwrap(),
+                parse_pubkey(args.value_of("gatekeeper-network").unwrap())?,
+                parse_pubkey(args.value_of("base-mint").unwrap())?,
+                lp_token_mint.pubkey(),
+            );
+            let ix = market.initialize_market(&payer.pubkey());
+            send(&rpc, &payer, &[&lp_token_mint], ix)?;
+            println!("Global market state: {}", market.global_market_state);
+        }
+        ("create-pass", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            for wallet in wallets(args)? {
+                let ix = market.create_credix_pass(&payer.pubkey(), &wallet);
+                send(&rpc, &payer, &[], ix)?;
+                println!("Created credix pass of {}", wallet);
+            }
+        }
+        ("update-pass", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            let active = args.value_of("active").unwrap() == "true";
+            for wallet in wallets(args)? {
+                let ix = market.update_credix_pass(&payer.pubkey(), &wallet, active);
+                send(&rpc, &payer, &[], ix)?;
+                println!("Updated credix pass of {}, active: {}", wallet, active);
+            }
+        }
+        ("close-pass", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            for wallet in wallets(args)? {
+                let ix = market.close_credix_pass(&payer.pubkey(), &wallet);
+                send(&rpc, &payer, &[], ix)?;
+                println!("Closed credix pass of {}", wallet);
+            }
+        }
+        ("update-deposit-cap", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            let max = parse_amount(args.value_of("max").unwrap())?;
+            let ix = market.update_deposit_cap(&payer.pubkey(), max);
+            send(&rpc, &payer, &[], ix)?;
+            println!("Updated deposit cap: {}", max);
+        }
+        ("update-investor-cap", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            let max = parse_amount(args.value_of("max").unwrap())?;
+            for wallet in wallets(args)? {
+                let ix = market.update_investor_deposit_cap(&payer.pubkey(), &wallet, max);
+                send(&rpc, &payer, &[], ix)?;
+                println!("Updated deposit cap of {}: {}", wallet, max);
+            }
+        }
+        ("update-withdraw-epochs", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            let duration = parse_duration(args.value_of("duration").unwrap())?;
+            let ix = market.update_withdraw_epochs(&payer.pubkey(), duration);
+            send(&rpc, &payer, &[], ix)?;
+            println!("Updated withdrawal epoch duration: {}s", duration);
+        }
+        ("update-lockup", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            let duration = parse_duration(args.value_of("duration").unwrap())?;
+            let ix = market.update_lp_lockup(&payer.pubkey(), duration);
+            send(&rpc, &payer, &[], ix)?;
+            println!("Updated LP lockup duration: {}s", duration);
+        }
+        ("close-withdraw-epoch", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            send(&rpc, &payer, &[], market.close_withdraw_epoch())?;
+            println!("Closed withdrawal epoch");
+        }
+        ("fill-withdraw-request", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            for wallet in wallets(args)? {
+                let ix = market.fill_withdraw_request(&payer.pubkey(), &wallet);
+                send(&rpc, &payer, &[], ix)?;
+                println!("Filled withdrawal request of {}", wallet);
+            }
+        }
+        ("refund-withdraw-request", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            for wallet in wallets(args)? {
+                let ix = market.refund_withdraw_request(&payer.pubkey(), &wallet);
+                send(&rpc, &payer, &[], ix)?;
+                println!("Refunded withdrawal request of {}", wallet);
+            }
+        }
+        ("migrate-market", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            let owner = parse_pubkey(args.value_of("owner").unwrap())?;
+            let ix = market.migrate_global_market_state(&payer.pubkey(), &owner);
+            send(&rpc, &payer, &[], ix)?;
+            println!(
+                "Migrated global market state {}",
+                market.global_market_state
+            );
+        }
+        ("migrate-pass", Some(args)) => {
+            let payer = payer(matches)?;
+            let market = load_market(&rpc, program_id, args)?;
+            for wallet in wallets(args)? {
+                let ix = market.migrate_credix_pass(&payer.pubkey(), &wallet);
+                send(&rpc, &payer, &[], ix)?;
+                println!("Migrated credix pass of {}", wallet);
+            }
+        }
+        ("create-signing-pda", Some(args)) => {
+            let payer = payer(matches)?;
+            let proxy_program_id = match args.value_of("proxy-program-id") {
+                Some(proxy_program_id) => parse_pubkey(proxy_program_id)?,
+                None => credix::permissioned_market_program::ID,
+            };
+            let ix = create_signing_authority(&proxy_program_id, &payer.pubkey());
+            send(&rpc, &payer, &[], ix)?;
+            println!("Created signing authority of {}", proxy_program_id);
+        }
+        ("show-market", Some(args)) => {
+            let (address, _) =
+                find_global_market_state(&program_id, args.value_of("seed").unwrap());
+            let state: GlobalMarketState = fetch(&rpc, &address)?;
+            println!("Global market state: {}", address);
+            println!("  owner: {}", state.owner);
+            println!("  gatekeeper network: {}", state.gatekeeper_network);
+            println!("  base mint: {}", state.liquidity_pool_token_mint_account);
+            println!("  LP token mint: {}", state.lp_token_mint_account);
+            println!("  pool value: {}", state.pool_value);
+            println!("  price band (bps): {}", state.price_band_bps);
+            println!("  trading halted: {}", state.trading_halted);
+            println!("  total deposits: {}", state.total_deposits);
+            println!("  deposit cap: {}", state.max_total_deposits);
+            println!("  LP lockup: {}s", state.lp_lockup_duration);
+            println!(
+                "  withdrawal epoch: {} (started {}, {}s)",
+                state.withdraw_epoch, state.withdraw_epoch_start, state.withdraw_epoch_duration
+            );
+            println!(
+                "  pending withdrawals: {} LP tokens in {} requests",
+                state.withdraw_pending_lp, state.withdraw_pending_requests
+            );
+            println!(
+                "  last fill: {} of {} LP tokens for {}, {} requests unfilled",
+                state.withdraw_fill_lp,
+                state.withdraw_closed_lp,
+                state.withdraw_fill_base,
+                state.withdraw_unfilled_requests
+            );
+            println!("  version: {}", state.version);
+        }
+        ("show-pass", Some(args)) => {
+            let market = load_market(&rpc, program_id, args)?;
+            let wallet = parse_pubkey(args.value_of("wallet").unwrap())?;
+            let address = market.credix_pass(&wallet);
+            let pass: CredixPass = fetch(&rpc, &address)?;
+            println!("Credix pass of {}: {}", wallet, address);
+            println!("  active: {}", pass.active);
+            println!("  total deposits: {}", pass.total_deposits);
+            println!("  deposit cap: {}", pass.max_deposits);
+            println!("  LP tokens locked until: {}", pass.locked_until);
+            println!("  version: {}", pass.version);
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Builds the market from its global market state on chain.
+fn load_market(rpc: &RpcClient, program_id: Pubkey, args: &ArgMatches) -> Result<CredixMarket> {
+    let seed = args.value_of("seed").unwrap();
+    let (address, _) = find_global_market_state(&program_id, seed);
+    // Markets are loaded before their migration too, only the leading fields
+    // are common to every layout.
+    let data = rpc
+        .get_account_data(&address)?
+        .ok_or_else(|| anyhow!("Account {} not found", address))?;
+    let fields = data
+        .strip_prefix(&GlobalMarketState::discriminator())
+        .filter(|fields| fields.len() >= 3 * 32)
+        .ok_or_else(|| anyhow!("Invalid account {}", address))?;
+    let pubkey = |index: usize| Pubkey::new(&fields[32 * index..32 * (index + 1)]);
+    // Gatekeeper network, base mint and LP token mint.
+    Ok(CredixMarket::with_program_id(
+        program_id,
+        seed,
+        pubkey(0),
+        pubkey(1),
+        pubkey(2),
+    ))
+}
+
+fn fetch<T: AccountDeserialize>(rpc: &RpcClient, address: &Pubkey) -> Result<T> {
+    let data = rpc
+        .get_account_data(address)?
+        .ok_or_else(|| anyhow!("Account {} not found", address))?;
+    T::try_deserialize(&mut data.as_slice())
+        .map_err(|err| anyhow!("Invalid account {}: {}", address, err))
+}
+
+fn send(rpc: &RpcClient, payer: &Keypair, signers: &[&Keypair], ix: Instruction) -> Result<()> {
+    let mut all_signers = vec![payer];
+    all_signers.extend(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &all_signers,
+        rpc.get_latest_blockhash()?,
+    );
+    let signature = rpc.send_and_confirm_transaction(&transaction)?;
+    println!("Signature: {}", signature);
+    Ok(())
+}
+
+/// Returns the wallet argument, or the wallets of the CSV file. The first
+/// line of the file is skipped when it isn't a wallet, as a header.
+fn wallets(args: &ArgMatches) -> Result<Vec<Pubkey>> {
+    let path = match args.value_of("csv") {
+        Some(path) => path,
+        None => return Ok(vec![parse_pubkey(args.value_of("wallet").unwrap())?]),
+    };
+    let csv = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let mut wallets = Vec::new();
+    for (i, line) in csv.lines().enumerate() {
+        let field = line.split(',').next().unwrap_or_default().trim();
+        if field.is_empty() {
+            continue;
+        }
+        match Pubkey::from_str(field) {
+            Ok(wallet) => wallets.push(wallet),
+            Err(_) if i == 0 => continue,
+            Err(_) => bail!("Invalid wallet on line {} of {}: {}", i + 1, path, field),
+        }
+    }
+    Ok(wallets)
+}
+
+fn payer(matches: &ArgMatches) -> Result<Keypair> {
+    match matches.value_of("keypair") {
+        Some(path) => read_keypair(path),
+        None => {
+            let home = std::env::var("HOME").context("HOME is not set")?;
+            read_keypair(&format!("{}/.config/solana/id.json", home))
+        }
+    }
+}
+
+fn read_keypair(path: &str) -> Result<Keypair> {
+    read_keypair_file(path).map_err(|err| anyhow!("Failed to read keypair {}: {}", path, err))
+}
+
+fn parse_amount(value: &str) -> Result<u64> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid amount: {}", value))
+}
+
+fn parse_duration(value: &str) -> Result<i64> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration: {}", value))
+}
+
+fn parse_pubkey(value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).map_err(|_| anyhow!("Invalid public key: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wallets of `create-pass` run with the given CSV file content.
+    fn wallets_of_csv(name: &str, csv: &str) -> Result<Vec<Pubkey>> {
+        let file = format!("credix-cli-{}-{}.csv", std::process::id(), name);
+        let path = std::env::temp_dir().join(file);
+        fs::write(&path, csv).unwrap();
+        let matches = app().get_matches_from(vec![
+            "credix",
+            "create-pass",
+            "--seed",
+            "credix-market",
+            "--csv",
+            path.to_str().unwrap(),
+        ]);
+        let result = wallets(matches.subcommand_matches("create-pass").unwrap());
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn wallets_are_read_from_the_first_column() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let csv = format!("wallet,name\n{},alice\n\n  \n{} , bob\n", alice, bob);
+
+        assert_eq!(wallets_of_csv("header", &csv).unwrap(), vec![alice, bob]);
+    }
+
+    #[test]
+    fn the_first_line_is_a_wallet_without_header() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let csv = format!("{}\n{}", alice, bob);
+
+        assert_eq!(wallets_of_csv("no-header", &csv).unwrap(), vec![alice, bob]);
+    }
+
+    #[test]
+    fn invalid_wallets_are_rejected_with_their_line() {
+        let csv = format!("wallet\n{}\nnot-a-wallet,carol\n", Pubkey::new_unique());
+
+        let err = wallets_of_csv("bad-row", &csv).unwrap_err().to_string();
+        assert!(err.starts_with("Invalid wallet on line 3 of "), "{}", err);
+        assert!(err.ends_with(": not-a-wallet"), "{}", err);
+    }
+
+    #[test]
+    fn a_single_wallet_is_taken_from_the_arguments() {
+        let wallet = Pubkey::new_unique().to_string();
+        let matches = app().get_matches_from(vec![
+            "credix",
+            "create-pass",
+            "--seed",
+            "credix-market",
+            &wallet,
+        ]);
+
+        let wallets = wallets(matches.subcommand_matches("create-pass").unwrap()).unwrap();
+        assert_eq!(wallets, vec![parse_pubkey(&wallet).unwrap()]);
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+// Time to wait for a transaction to be confirmed.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Minimal JSON RPC client, covering what the CLI needs.
+///
+/// `solana-client` pulls in the hardware wallet support of the Solana CLI,
+/// which links against libudev.
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+        }
+    }
+
+    /// Returns the data of an account, `None` if it doesn't exist.
+    pub fn get_account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let result = self.request(
+            "getAccountInfo",
+            json!([pubkey.to_string(), { "encoding": "base64", "commitment": "confirmed" }]),
+        )?;
+        match &result["value"] {
+            Value::Null => Ok(None),
+            value => {
+                let data = value["data"][0]
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Unexpected account encoding"))?;
+                Ok(Some(base64::decode(data)?))
+            }
+        }
+    }
+
+    pub fn get_latest_blockhash(&self) -> Result<Hash> {
+        let result = self.request("getLatestBlockhash", json!([{ "commitment": "confirmed" }]))?;
+        let blockhash = result["value"]["blockhash"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Missing blockhash"))?;
+        Ok(Hash::from_str(blockhash)?)
+    }
+
+    pub fn send_and_confirm_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+        let encoded = base64::encode(bincode::serialize(transaction)?);
+        let result = self.request(
+            "sendTransaction",
+            json!([encoded, { "encoding": "base64", "preflightCommitment": "confirmed" }]),
+        )?;
+        let signature = Signature::from_str(
+            result
+                .as_str()
+                .ok_or_else(|| anyhow!("Missing transaction signature"))?,
+        )?;
+
+        let start = Instant::now();
+        while start.elapsed() < CONFIRMATION_TIMEOUT {
+            let result = self.request("getSignatureStatuses", json!([[signature.to_string()]]))?;
+            let status = &result["value"][0];
+            if !status.is_null() {
+                if !status["err"].is_null() {
+                    bail!("Transaction {} failed: {}", signature, status["err"]);
+                }
+                if let Some("confirmed") | Some("finalized") = status["confirmationStatus"].as_str()
+                {
+                    return Ok(signature);
+                }
+            }
+            sleep(Duration::from_millis(500));
+        }
+        bail!("Transaction {} was not confirmed in time", signature)
+    }
+
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = ureq::post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .with_context(|| format!("{} request to {} failed", method, self.url))?
+            .into_json()?;
+        if !response["error"].is_null() {
+            bail!("{} failed: {}", method, response["error"]);
+        }
+        Ok(response["result"].clone())
+    }
+}
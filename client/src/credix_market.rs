@@ -0,0 +1,423 @@
+use crate::pda::*;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::InstructionData;
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token;
+
+/// A credix market, identified by the seed of its global market state.
+///
+/// `freeze_lp_tokens` and `thaw_lp_tokens` have no builder: only the
+/// permissioned market can sign them, see `PermissionedMarket`.
+#[derive(Clone, Debug)]
+pub struct CredixMarket {
+    pub program_id: Pubkey,
+    pub global_market_seed: String,
+    pub global_market_state: Pubkey,
+    pub gatekeeper_network: Pubkey,
+    pub base_mint: Pubkey,
+    pub lp_token_mint: Pubkey,
+}
+
+impl CredixMarket {
+    pub fn new(
+        global_market_seed: &str,
+        gatekeeper_network: Pubkey,
+        base_mint: Pubkey,
+        lp_token_mint: Pubkey,
+    ) -> Self {
+        Self::with_program_id(
+            credix::ID,
+            global_market_seed,
+            gatekeeper_network,
+            base_mint,
+            lp_token_mint,
+        )
+    }
+
+    /// Constructs a market of a credix program deployed at another address.
+    pub fn with_program_id(
+        program_id: Pubkey,
+        global_market_seed: &str,
+        gatekeeper_network: Pubkey,
+        base_mint: Pubkey,
+        lp_token_mint: Pubkey,
+    ) -> Self {
+        Self {
+            program_id,
+            global_market_seed: global_market_seed.to_string(),
+            global_market_state: find_global_market_state(&program_id, global_market_seed).0,
+            gatekeeper_network,
+            base_mint,
+            lp_token_mint,
+        }
+    }
+
+    pub fn signing_authority(&self) -> Pubkey {
+        find_signing_authority(&self.program_id, &self.global_market_state).0
+    }
+
+    pub fn credix_pass(&self, pass_holder: &Pubkey) -> Pubkey {
+        find_credix_pass(&self.program_id, &self.global_market_state, pass_holder).0
+    }
+
+    pub fn gateway_token(&self, user: &Pubkey) -> Pubkey {
+        find_gateway_token(user, &self.gatekeeper_network).0
+    }
+
+    pub fn lp_token_account(&self, user: &Pubkey) -> Pubkey {
+        get_associated_token_address(user, &self.lp_token_mint)
+    }
+
+    pub fn liquidity_pool_token_account(&self) -> Pubkey {
+        get_associated_token_address(&self.signing_authority(), &self.base_mint)
+    }
+
+    pub fn withdraw_request(&self, investor: &Pubkey) -> Pubkey {
+        find_withdraw_request(&self.program_id, &self.global_market_state, investor).0
+    }
+
+    /// The LP token mint is created by the instruction and must sign it.
+    pub fn initialize_market(&self, owner: &Pubkey) -> Instruction {
+        let (_, global_market_state_bump) =
+            find_global_market_state(&self.program_id, &self.global_market_seed);
+        let (signing_authority, signing_authority_bump) =
+            find_signing_authority(&self.program_id, &self.global_market_state);
+        let accounts = credix::accounts::InitializeMarket {
+            owner: *owner,
+            gatekeeper_network: self.gatekeeper_network,
+            global_market_state: self.global_market_state,
+            signing_authority,
+            liquidity_pool_token_account: get_associated_token_address(
+                &signing_authority,
+                &self.base_mint,
+            ),
+            lp_token_mint_account: self.lp_token_mint,
+            base_mint_account: self.base_mint,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        };
+        let data = credix::instruction::InitializeMarket {
+            signing_authority_bump,
+            global_market_state_bump,
+            _global_market_seed: self.global_market_seed.clone(),
+        };
+        self.instruction(accounts, data)
+    }
+
+    pub fn deposit_funds(
+        &self,
+        investor: &Pubkey,
+        investor_token_account: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let signing_authority = self.signing_authority();
+        let accounts = credix::accounts::DepositFunds {
+            investor: *investor,
+            gateway_token: self.gateway_token(investor),
+            global_market_state: self.global_market_state,
+            signing_authority,
+            investor_token_account: *investor_token_account,
+            liquidity_pool_token_account: get_associated_token_address(
+                &signing_authority,
+                &self.base_mint,
+            ),
+            lp_token_mint_account: self.lp_token_mint,
+            investor_lp_token_account: self.lp_token_account(investor),
+            credix_pass: self.credix_pass(investor),
+            base_mint_account: self.base_mint,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        };
+        self.instruction(accounts, credix::instruction::DepositFunds { amount })
+    }
+
+    pub fn create_credix_pass(&self, owner: &Pubkey, pass_holder: &Pubkey) -> Instruction {
+        let (credix_pass, pass_bump) =
+            find_credix_pass(&self.program_id, &self.global_market_state, pass_holder);
+        let accounts = credix::accounts::CreateCredixPass {
+            owner: *owner,
+            pass_holder: *pass_holder,
+            credix_pass,
+            global_market_state: self.global_market_state,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::CreateCredixPass { pass_bump },
+        )
+    }
+
+    pub fn update_credix_pass(
+        &self,
+        owner: &Pubkey,
+        pass_holder: &Pubkey,
+        is_active: bool,
+    ) -> Instruction {
+        let accounts = credix::accounts::UpdateCredixPass {
+            owner: *owner,
+            pass_holder: *pass_holder,
+            credix_pass: self.credix_pass(pass_holder),
+            global_market_state: self.global_market_state,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::UpdateCredixPass { is_active },
+        )
+    }
+
+    /// Only the owner of the market can close credix passes.
+    pub fn close_credix_pass(&self, owner: &Pubkey, pass_holder: &Pubkey) -> Instruction {
+        let accounts = credix::accounts::CloseCredixPass {
+            owner: *owner,
+            pass_holder: *pass_holder,
+            credix_pass: self.credix_pass(pass_holder),
+            global_market_state: self.global_market_state,
+        };
+        self.instruction(accounts, credix::instruction::CloseCredixPass)
+    }
+
+    pub fn update_price_band(
+        &self,
+        owner: &Pubkey,
+        price_band_bps: u16,
+        trading_halted: bool,
+    ) -> Instruction {
+        let accounts = credix::accounts::UpdatePriceBand {
+            owner: *owner,
+            global_market_state: self.global_market_state,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::UpdatePriceBand {
+                price_band_bps,
+                trading_halted,
+            },
+        )
+    }
+
+    /// Caps the deposits of the pool, zero disables the cap.
+    pub fn update_deposit_cap(&self, owner: &Pubkey, max_total_deposits: u64) -> Instruction {
+        let accounts = credix::accounts::UpdateDepositCap {
+            owner: *owner,
+            global_market_state: self.global_market_state,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::UpdateDepositCap { max_total_deposits },
+        )
+    }
+
+    /// Caps the deposits of a pass holder, zero disables the cap.
+    pub fn update_investor_deposit_cap(
+        &self,
+        owner: &Pubkey,
+        pass_holder: &Pubkey,
+        max_deposits: u64,
+    ) -> Instruction {
+        let accounts = credix::accounts::UpdateInvestorDepositCap {
+            owner: *owner,
+            pass_holder: *pass_holder,
+            credix_pass: self.credix_pass(pass_holder),
+            global_market_state: self.global_market_state,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::UpdateInvestorDepositCap { max_deposits },
+        )
+    }
+
+    /// Locks the LP tokens of investors for `lockup_duration` seconds after
+    /// each of their deposits, zero disables the lockup.
+    pub fn update_lp_lockup(&self, owner: &Pubkey, lockup_duration: i64) -> Instruction {
+        let accounts = credix::accounts::UpdateLpLockup {
+            owner: *owner,
+            global_market_state: self.global_market_state,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::UpdateLpLockup { lockup_duration },
+        )
+    }
+
+    /// Sets the duration of withdrawal epochs in seconds, zero stops new
+    /// requests.
+    pub fn update_withdraw_epochs(&self, owner: &Pubkey, epoch_duration: i64) -> Instruction {
+        let accounts = credix::accounts::UpdateWithdrawEpochs {
+            owner: *owner,
+            global_market_state: self.global_market_state,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::UpdateWithdrawEpochs { epoch_duration },
+        )
+    }
+
+    /// Locks LP tokens of the investor in escrow until the current epoch is
+    /// closed and the request filled.
+    pub fn create_withdraw_request(&self, investor: &Pubkey, amount: u64) -> Instruction {
+        let withdraw_request = self.withdraw_request(investor);
+        let accounts = credix::accounts::CreateWithdrawRequest {
+            investor: *investor,
+            global_market_state: self.global_market_state,
+            signing_authority: self.signing_authority(),
+            withdraw_request,
+            credix_pass: self.credix_pass(investor),
+            escrow: find_withdraw_escrow(&self.program_id, &withdraw_request).0,
+            investor_lp_token_account: self.lp_token_account(investor),
+            lp_token_mint_account: self.lp_token_mint,
+            rent: sysvar::rent::ID,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        };
+        self.instruction(
+            accounts,
+            credix::instruction::CreateWithdrawRequest { amount },
+        )
+    }
+
+    /// Closes the current withdrawal epoch once over, anyone can crank it.
+    pub fn close_withdraw_epoch(&self) -> Instruction {
+        let accounts = credix::accounts::CloseWithdrawEpoch {
+            global_market_state: self.global_market_state,
+            signing_authority: self.signing_authority(),
+            liquidity_pool_token_account: self.liquidity_pool_token_account(),
+            lp_token_mint_account: self.lp_token_mint,
+        };
+        self.instruction(accounts, credix::instruction::CloseWithdrawEpoch)
+    }
+
+    /// Fills the request of an investor in the last closed epoch into their
+    /// base token account, anyone can crank it. `payer` creates the account
+    /// if needed.
+    pub fn fill_withdraw_request(&self, payer: &Pubkey, investor: &Pubkey) -> Instruction {
+        let withdraw_request = self.withdraw_request(investor);
+        let accounts = credix::accounts::FillWithdrawRequest {
+            payer: *payer,
+            global_market_state: self.global_market_state,
+            signing_authority: self.signing_authority(),
+            withdraw_request,
+            escrow: find_withdraw_escrow(&self.program_id, &withdraw_request).0,
+            investor: *investor,
+            credix_pass: self.credix_pass(investor),
+            investor_token_account: get_associated_token_address(investor, &self.base_mint),
+            liquidity_pool_token_account: self.liquidity_pool_token_account(),
+            lp_token_mint_account: self.lp_token_mint,
+            base_mint_account: self.base_mint,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        };
+        self.instruction(accounts, credix::instruction::FillWithdrawRequest)
+    }
+
+    /// Returns the LP tokens of a request pending in the current epoch to
+    /// the investor.
+    pub fn cancel_withdraw_request(&self, investor: &Pubkey) -> Instruction {
+        let withdraw_request = self.withdraw_request(investor);
+        let accounts = credix::accounts::CancelWithdrawRequest {
+            investor: *investor,
+            global_market_state: self.global_market_state,
+            signing_authority: self.signing_authority(),
+            withdraw_request,
+            escrow: find_withdraw_escrow(&self.program_id, &withdraw_request).0,
+            investor_lp_token_account: self.lp_token_account(investor),
+            lp_token_mint_account: self.lp_token_mint,
+            token_program: token::ID,
+        };
+        self.instruction(accounts, credix::instruction::CancelWithdrawRequest)
+    }
+
+    /// Returns the LP tokens of a request to the investor, pending or left
+    /// unfilled in the last closed epoch. Only the owner of the market can
+    /// refund requests.
+    pub fn refund_withdraw_request(&self, owner: &Pubkey, investor: &Pubkey) -> Instruction {
+        let withdraw_request = self.withdraw_request(investor);
+        let accounts = credix::accounts::RefundWithdrawRequest {
+            owner: *owner,
+            global_market_state: self.global_market_state,
+            signing_authority: self.signing_authority(),
+            investor: *investor,
+            withdraw_request,
+            escrow: find_withdraw_escrow(&self.program_id, &withdraw_request).0,
+            investor_lp_token_account: self.lp_token_account(investor),
+            lp_token_mint_account: self.lp_token_mint,
+            token_program: token::ID,
+        };
+        self.instruction(accounts, credix::instruction::RefundWithdrawRequest)
+    }
+
+    /// Approves `delegate` on the frozen LP tokens of `lp_holder`, e.g. their
+    /// open orders account for asks of a trading delegate.
+    pub fn approve_lp_tokens(
+        &self,
+        lp_holder: &Pubkey,
+        delegate: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let accounts = credix::accounts::ApproveLpTokens {
+            lp_holder: *lp_holder,
+            delegate: *delegate,
+            credix_pass: self.credix_pass(lp_holder),
+            lp_token_account: self.lp_token_account(lp_holder),
+            global_market_state: self.global_market_state,
+            signing_authority: self.signing_authority(),
+            lp_token_mint_account: self.lp_token_mint,
+            token_program: token::ID,
+        };
+        self.instruction(accounts, credix::instruction::ApproveLpTokens { amount })
+    }
+
+    /// Grows the global market state to the current layout, the upgrade
+    /// authority of the credix program paying for the rent. `owner` is
+    /// recorded on markets created before their owner was, and must be the
+    /// recorded one otherwise.
+    pub fn migrate_global_market_state(
+        &self,
+        upgrade_authority: &Pubkey,
+        owner: &Pubkey,
+    ) -> Instruction {
+        let accounts = credix::accounts::MigrateGlobalMarketState {
+            upgrade_authority: *upgrade_authority,
+            program_data: find_program_data(&self.program_id).0,
+            owner: *owner,
+            global_market_state: self.global_market_state,
+            signing_authority: self.signing_authority(),
+            liquidity_pool_token_account: self.liquidity_pool_token_account(),
+            system_program: system_program::ID,
+        };
+        self.instruction(accounts, credix::instruction::MigrateGlobalMarketState)
+    }
+
+    /// Grows a credix pass to the current layout, the owner of the market
+    /// paying for the rent.
+    pub fn migrate_credix_pass(&self, owner: &Pubkey, pass_holder: &Pubkey) -> Instruction {
+        let accounts = credix::accounts::MigrateCredixPass {
+            owner: *owner,
+            pass_holder: *pass_holder,
+            credix_pass: self.credix_pass(pass_holder),
+            global_market_state: self.global_market_state,
+            system_program: system_program::ID,
+        };
+        self.instruction(accounts, credix::instruction::MigrateCredixPass)
+    }
+
+    fn instruction(
+        &self,
+        accounts: impl ToAccountMetas,
+        data: impl InstructionData,
+    ) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+}
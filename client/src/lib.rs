@@ -0,0 +1,11 @@
+//! Builds the instructions of the credix program and of the credix
+//! permissioned market proxy, including the credix accounts and instruction
+//! data prefixed to every proxied DEX instruction.
+
+mod credix_market;
+mod pda;
+mod permissioned_market;
+
+pub use credix_market::*;
+pub use pda::*;
+pub use permissioned_market::*;
@@ -0,0 +1,136 @@
+use anchor_lang::prelude::*;
+use credix::{CREDIX_PASS_SEED, WITHDRAW_ESCROW_SEED, WITHDRAW_REQUEST_SEED};
+use credix_permissioned_market::ALLOWLIST_SEED;
+use serum_dex_permissioned::{open_orders_index_seed, TRADING_DELEGATE_SEED};
+
+/// Global market state of the credix market created with the given seed.
+pub fn find_global_market_state(
+    credix_program_id: &Pubkey,
+    global_market_seed: &str,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[global_market_seed.as_bytes()], credix_program_id)
+}
+
+/// Signing authority of a credix market, owning the liquidity pool and the
+/// LP token mint.
+pub fn find_signing_authority(
+    credix_program_id: &Pubkey,
+    global_market_state: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[global_market_state.as_ref()], credix_program_id)
+}
+
+pub fn find_credix_pass(
+    credix_program_id: &Pubkey,
+    global_market_state: &Pubkey,
+    pass_holder: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            global_market_state.as_ref(),
+            pass_holder.as_ref(),
+            CREDIX_PASS_SEED.as_bytes(),
+        ],
+        credix_program_id,
+    )
+}
+
+/// Withdrawal request of an investor, reused by their successive requests.
+pub fn find_withdraw_request(
+    credix_program_id: &Pubkey,
+    global_market_state: &Pubkey,
+    investor: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            global_market_state.as_ref(),
+            investor.as_ref(),
+            WITHDRAW_REQUEST_SEED.as_bytes(),
+        ],
+        credix_program_id,
+    )
+}
+
+/// Token account escrowing the LP tokens of a withdrawal request.
+pub fn find_withdraw_escrow(credix_program_id: &Pubkey, withdraw_request: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[withdraw_request.as_ref(), WITHDRAW_ESCROW_SEED.as_bytes()],
+        credix_program_id,
+    )
+}
+
+/// Signing authority of the permissioned market, the only signer allowed to
+/// thaw and freeze LP tokens.
+pub fn find_permissioned_signing_authority(proxy_program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"signing-authority"], proxy_program_id)
+}
+
+pub fn find_open_orders(
+    proxy_program_id: &Pubkey,
+    dex_program_id: &Pubkey,
+    market: &Pubkey,
+    authority: &Pubkey,
+    index: u8,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"open-orders",
+            dex_program_id.as_ref(),
+            market.as_ref(),
+            authority.as_ref(),
+            open_orders_index_seed(index).as_ref(),
+        ],
+        proxy_program_id,
+    )
+}
+
+pub fn find_open_orders_init_authority(
+    proxy_program_id: &Pubkey,
+    dex_program_id: &Pubkey,
+    market: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"open-orders-init",
+            dex_program_id.as_ref(),
+            market.as_ref(),
+        ],
+        proxy_program_id,
+    )
+}
+
+pub fn find_allowlist(proxy_program_id: &Pubkey, market: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ALLOWLIST_SEED.as_bytes(), market.as_ref()],
+        proxy_program_id,
+    )
+}
+
+pub fn find_allowlist_entry(
+    proxy_program_id: &Pubkey,
+    market: &Pubkey,
+    user: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ALLOWLIST_SEED.as_bytes(), market.as_ref(), user.as_ref()],
+        proxy_program_id,
+    )
+}
+
+pub fn find_trading_delegate(proxy_program_id: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TRADING_DELEGATE_SEED, owner.as_ref()], proxy_program_id)
+}
+
+/// Program data account of an upgradeable program, holding its upgrade
+/// authority.
+pub fn find_program_data(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[program_id.as_ref()],
+        &anchor_lang::solana_program::bpf_loader_upgradeable::ID,
+    )
+}
+
+/// Civic gateway token of a user, without additional seed.
+pub fn find_gateway_token(user: &Pubkey, gatekeeper_network: &Pubkey) -> (Pubkey, u8) {
+    solana_gateway::state::get_gateway_token_address_with_seed(user, &None, gatekeeper_network)
+}
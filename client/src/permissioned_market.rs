@@ -0,0 +1,515 @@
+use crate::credix_market::CredixMarket;
+use crate::pda::*;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::InstructionData;
+use anchor_spl::{associated_token, token};
+use serum_dex_permissioned::serum_dex::instruction::{
+    CancelOrderInstructionV2, MarketInstruction, NewOrderInstructionV3,
+};
+
+/// Creates the signing authority of the permissioned market, once per
+/// deployment.
+pub fn create_signing_authority(proxy_program_id: &Pubkey, payer: &Pubkey) -> Instruction {
+    let (signing_pda, bump) = find_permissioned_signing_authority(proxy_program_id);
+    // Handled by the fallback of the proxy, next to the proxied DEX
+    // instructions.
+    Instruction {
+        program_id: *proxy_program_id,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(signing_pda, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(sysvar::rent::ID, false),
+        ],
+        data: vec![255, bump],
+    }
+}
+
+/// Accounts of the serum market traded through the permissioned market.
+#[derive(Clone, Copy, Debug)]
+pub struct MarketAccounts {
+    pub market: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub vault_signer: Pubkey,
+}
+
+/// The LP token market of a credix market, traded through the credix
+/// permissioned market proxy.
+///
+/// Proxied DEX instructions pass the DEX program, the credix accounts of
+/// `CredixPermissionedMarket`, the allowlist accounts (`init_open_orders` and
+/// `new_order_v3` only), the DEX accounts and, for delegated instructions, the
+/// trading delegate and the delegate.
+///
+/// Their data starts with the signing authority bump and the `OpenOrdersPda`
+/// discriminator, followed by the packed DEX instruction. The market cranks,
+/// `consume_events`, `consume_events_permissioned` and `prune`, pass the DEX
+/// accounts only.
+#[derive(Clone, Debug)]
+pub struct PermissionedMarket {
+    pub program_id: Pubkey,
+    pub dex_program_id: Pubkey,
+    pub credix: CredixMarket,
+    pub accounts: MarketAccounts,
+}
+
+impl PermissionedMarket {
+    pub fn new(credix: CredixMarket, dex_program_id: Pubkey, accounts: MarketAccounts) -> Self {
+        Self {
+            program_id: credix_permissioned_market::ID,
+            dex_program_id,
+            credix,
+            accounts,
+        }
+    }
+
+    /// Builder method for a proxy program deployed at another address.
+    pub fn program_id(mut self, program_id: Pubkey) -> Self {
+        self.program_id = program_id;
+        self
+    }
+
+    pub fn open_orders(&self, owner: &Pubkey, index: u8) -> Pubkey {
+        find_open_orders(
+            &self.program_id,
+            &self.dex_program_id,
+            &self.accounts.market,
+            owner,
+            index,
+        )
+        .0
+    }
+
+    pub fn create_allowlist(&self, authority: &Pubkey) -> Instruction {
+        let (allowlist, bump) = find_allowlist(&self.program_id, &self.accounts.market);
+        let accounts = credix_permissioned_market::accounts::CreateAllowlist {
+            authority: *authority,
+            global_market_state: self.credix.global_market_state,
+            market: self.accounts.market,
+            allowlist,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        };
+        self.instruction(
+            accounts,
+            credix_permissioned_market::instruction::CreateAllowlist { bump },
+        )
+    }
+
+    pub fn close_allowlist(&self, authority: &Pubkey) -> Instruction {
+        let accounts = credix_permissioned_market::accounts::CloseAllowlist {
+            authority: *authority,
+            allowlist: find_allowlist(&self.program_id, &self.accounts.market).0,
+        };
+        self.instruction(
+            accounts,
+            credix_permissioned_market::instruction::CloseAllowlist,
+        )
+    }
+
+    pub fn add_to_allowlist(&self, authority: &Pubkey, user: &Pubkey) -> Instruction {
+        let (allowlist_entry, bump) =
+            find_allowlist_entry(&self.program_id, &self.accounts.market, user);
+        let accounts = credix_permissioned_market::accounts::AddToAllowlist {
+            authority: *authority,
+            allowlist: find_allowlist(&self.program_id, &self.accounts.market).0,
+            user: *user,
+            allowlist_entry,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        };
+        self.instruction(
+            accounts,
+            credix_permissioned_market::instruction::AddToAllowlist { bump },
+        )
+    }
+
+    pub fn remove_from_allowlist(&self, authority: &Pubkey, user: &Pubkey) -> Instruction {
+        let accounts = credix_permissioned_market::accounts::RemoveFromAllowlist {
+            authority: *authority,
+            allowlist: find_allowlist(&self.program_id, &self.accounts.market).0,
+            allowlist_entry: find_allowlist_entry(&self.program_id, &self.accounts.market, user).0,
+        };
+        self.instruction(
+            accounts,
+            credix_permissioned_market::instruction::RemoveFromAllowlist,
+        )
+    }
+
+    pub fn set_trading_delegate(
+        &self,
+        owner: &Pubkey,
+        delegate: Pubkey,
+        permissions: u8,
+        expiry: i64,
+    ) -> Instruction {
+        let (trading_delegate, bump) = find_trading_delegate(&self.program_id, owner);
+        let accounts = credix_permissioned_market::accounts::SetTradingDelegate {
+            owner: *owner,
+            trading_delegate,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        };
+        self.instruction(
+            accounts,
+            credix_permissioned_market::instruction::SetTradingDelegate {
+                bump,
+                delegate,
+                permissions,
+                expiry,
+            },
+        )
+    }
+
+    pub fn revoke_trading_delegate(&self, owner: &Pubkey) -> Instruction {
+        let accounts = credix_permissioned_market::accounts::RevokeTradingDelegate {
+            owner: *owner,
+            trading_delegate: find_trading_delegate(&self.program_id, owner).0,
+        };
+        self.instruction(
+            accounts,
+            credix_permissioned_market::instruction::RevokeTradingDelegate,
+        )
+    }
+
+    /// Initializes the open orders account of `owner` with the given index.
+    pub fn init_open_orders(&self, owner: &Pubkey, index: u8) -> Instruction {
+        let market = &self.accounts.market;
+        let (open_orders, bump) =
+            find_open_orders(&self.program_id, &self.dex_program_id, market, owner, index);
+        let (open_orders_init_authority, bump_init) =
+            find_open_orders_init_authority(&self.program_id, &self.dex_program_id, market);
+        let dex_accounts = vec![
+            AccountMeta::new_readonly(self.dex_program_id, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new(open_orders, false),
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(*market, false),
+            AccountMeta::new_readonly(sysvar::rent::ID, false),
+            AccountMeta::new_readonly(open_orders_init_authority, false),
+        ];
+        let discriminator = match index {
+            0 => vec![0, bump, bump_init],
+            _ => vec![2, bump, bump_init, index],
+        };
+        self.proxy(
+            owner,
+            None,
+            true,
+            dex_accounts,
+            discriminator,
+            MarketInstruction::InitOpenOrders,
+        )
+    }
+
+    /// Places an order from the open orders account of `owner` with the given
+    /// index, signed by `delegate` when given.
+    pub fn new_order_v3(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        order_payer: &Pubkey,
+        delegate: Option<&Pubkey>,
+        ix: NewOrderInstructionV3,
+    ) -> Instruction {
+        let dex_accounts = vec![
+            AccountMeta::new(self.accounts.market, false),
+            AccountMeta::new(self.open_orders(owner, index), false),
+            AccountMeta::new(self.accounts.request_queue, false),
+            AccountMeta::new(self.accounts.event_queue, false),
+            AccountMeta::new(self.accounts.bids, false),
+            AccountMeta::new(self.accounts.asks, false),
+            AccountMeta::new(*order_payer, false),
+            AccountMeta::new_readonly(*owner, delegate.is_none()),
+            AccountMeta::new(self.accounts.coin_vault, false),
+            AccountMeta::new(self.accounts.pc_vault, false),
+            AccountMeta::new_readonly(token::ID, false),
+            AccountMeta::new_readonly(sysvar::rent::ID, false),
+        ];
+        self.proxy(
+            owner,
+            delegate,
+            true,
+            dex_accounts,
+            Self::discriminator(index, delegate),
+            MarketInstruction::NewOrderV3(ix),
+        )
+    }
+
+    pub fn cancel_order_v2(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        delegate: Option<&Pubkey>,
+        ix: CancelOrderInstructionV2,
+    ) -> Instruction {
+        self.proxy(
+            owner,
+            delegate,
+            false,
+            self.cancel_accounts(owner, index, delegate),
+            Self::discriminator(index, delegate),
+            MarketInstruction::CancelOrderV2(ix),
+        )
+    }
+
+    pub fn cancel_order_by_client_id_v2(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        delegate: Option<&Pubkey>,
+        client_id: u64,
+    ) -> Instruction {
+        self.proxy(
+            owner,
+            delegate,
+            false,
+            self.cancel_accounts(owner, index, delegate),
+            Self::discriminator(index, delegate),
+            MarketInstruction::CancelOrderByClientIdV2(client_id),
+        )
+    }
+
+    /// Settles the funds of an open orders account, `referral_pc_wallet` must
+    /// be owned by the referral of the permissioned market.
+    pub fn settle_funds(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        coin_wallet: &Pubkey,
+        pc_wallet: &Pubkey,
+        referral_pc_wallet: &Pubkey,
+    ) -> Instruction {
+        let dex_accounts = vec![
+            AccountMeta::new(self.accounts.market, false),
+            AccountMeta::new(self.open_orders(owner, index), false),
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(self.accounts.coin_vault, false),
+            AccountMeta::new(self.accounts.pc_vault, false),
+            AccountMeta::new(*coin_wallet, false),
+            AccountMeta::new(*pc_wallet, false),
+            AccountMeta::new_readonly(self.accounts.vault_signer, false),
+            AccountMeta::new_readonly(token::ID, false),
+            AccountMeta::new(*referral_pc_wallet, false),
+        ];
+        self.proxy(
+            owner,
+            None,
+            false,
+            dex_accounts,
+            Self::discriminator(index, None),
+            MarketInstruction::SettleFunds,
+        )
+    }
+
+    pub fn close_open_orders(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        destination: &Pubkey,
+    ) -> Instruction {
+        let dex_accounts = vec![
+            AccountMeta::new(self.open_orders(owner, index), false),
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(self.accounts.market, false),
+        ];
+        self.proxy(
+            owner,
+            None,
+            false,
+            dex_accounts,
+            Self::discriminator(index, None),
+            MarketInstruction::CloseOpenOrders,
+        )
+    }
+
+    /// Consumes the events of the given open orders accounts.
+    pub fn consume_events(
+        &self,
+        open_orders: &[Pubkey],
+        coin_fee_receivable: &Pubkey,
+        pc_fee_receivable: &Pubkey,
+        limit: u16,
+    ) -> Instruction {
+        let mut dex_accounts: Vec<AccountMeta> = open_orders
+            .iter()
+            .map(|open_orders| AccountMeta::new(*open_orders, false))
+            .collect();
+        dex_accounts.extend(vec![
+            AccountMeta::new(self.accounts.market, false),
+            AccountMeta::new(self.accounts.event_queue, false),
+            AccountMeta::new(*coin_fee_receivable, false),
+            AccountMeta::new(*pc_fee_receivable, false),
+        ]);
+        self.relay(dex_accounts, MarketInstruction::ConsumeEvents(limit))
+    }
+
+    /// Consumes the events of the given open orders accounts, signed by the
+    /// consume events authority of the market.
+    pub fn consume_events_permissioned(
+        &self,
+        open_orders: &[Pubkey],
+        consume_events_authority: &Pubkey,
+        limit: u16,
+    ) -> Instruction {
+        let mut dex_accounts: Vec<AccountMeta> = open_orders
+            .iter()
+            .map(|open_orders| AccountMeta::new(*open_orders, false))
+            .collect();
+        dex_accounts.extend(vec![
+            AccountMeta::new(self.accounts.market, false),
+            AccountMeta::new(self.accounts.event_queue, false),
+            AccountMeta::new_readonly(*consume_events_authority, true),
+        ]);
+        self.relay(
+            dex_accounts,
+            MarketInstruction::ConsumeEventsPermissioned(limit),
+        )
+    }
+
+    /// Cancels the orders of the open orders account of `owner` with the
+    /// given index, signed by the prune authority of the market.
+    pub fn prune(
+        &self,
+        prune_authority: &Pubkey,
+        owner: &Pubkey,
+        index: u8,
+        limit: u16,
+    ) -> Instruction {
+        // `OpenOrdersPda` replaces the owner with the open orders account.
+        let dex_accounts = vec![
+            AccountMeta::new(self.accounts.market, false),
+            AccountMeta::new(self.accounts.bids, false),
+            AccountMeta::new(self.accounts.asks, false),
+            AccountMeta::new_readonly(*prune_authority, true),
+            AccountMeta::new(self.open_orders(owner, index), false),
+            AccountMeta::new_readonly(*owner, false),
+            AccountMeta::new(self.accounts.event_queue, false),
+        ];
+        self.relay(dex_accounts, MarketInstruction::Prune(limit))
+    }
+
+    fn cancel_accounts(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        delegate: Option<&Pubkey>,
+    ) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.accounts.market, false),
+            AccountMeta::new(self.accounts.bids, false),
+            AccountMeta::new(self.accounts.asks, false),
+            AccountMeta::new(self.open_orders(owner, index), false),
+            AccountMeta::new_readonly(*owner, delegate.is_none()),
+            AccountMeta::new(self.accounts.event_queue, false),
+        ]
+    }
+
+    fn discriminator(index: u8, delegate: Option<&Pubkey>) -> Vec<u8> {
+        match (delegate, index) {
+            (Some(_), _) => vec![4, index],
+            (None, 0) => vec![1],
+            (None, _) => vec![3, index],
+        }
+    }
+
+    /// Wraps DEX accounts and instruction into an instruction of the proxy.
+    fn proxy(
+        &self,
+        owner: &Pubkey,
+        delegate: Option<&Pubkey>,
+        allowlisted: bool,
+        dex_accounts: Vec<AccountMeta>,
+        discriminator: Vec<u8>,
+        ix: MarketInstruction,
+    ) -> Instruction {
+        let credix = &self.credix;
+        let (permissioned_signing_authority, signing_authority_bump) =
+            find_permissioned_signing_authority(&self.program_id);
+
+        let mut accounts = vec![
+            AccountMeta::new_readonly(self.dex_program_id, false),
+            AccountMeta::new(*owner, delegate.is_none()),
+            AccountMeta::new(credix.lp_token_account(owner), false),
+            AccountMeta::new_readonly(permissioned_signing_authority, false),
+            AccountMeta::new_readonly(credix.signing_authority(), false),
+            AccountMeta::new_readonly(credix.lp_token_mint, false),
+            AccountMeta::new_readonly(credix.global_market_state, false),
+            AccountMeta::new_readonly(credix.credix_pass(owner), false),
+            AccountMeta::new_readonly(token::ID, false),
+            AccountMeta::new_readonly(credix.program_id, false),
+            AccountMeta::new_readonly(associated_token::ID, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            AccountMeta::new_readonly(sysvar::rent::ID, false),
+            AccountMeta::new_readonly(credix.gateway_token(owner), false),
+        ];
+        if allowlisted {
+            let market = &self.accounts.market;
+            accounts.push(AccountMeta::new_readonly(
+                find_allowlist(&self.program_id, market).0,
+                false,
+            ));
+            accounts.push(AccountMeta::new_readonly(
+                find_allowlist_entry(&self.program_id, market, owner).0,
+                false,
+            ));
+        }
+        accounts.extend(dex_accounts);
+        if let Some(delegate) = delegate {
+            accounts.push(AccountMeta::new_readonly(
+                find_trading_delegate(&self.program_id, owner).0,
+                false,
+            ));
+            accounts.push(AccountMeta::new_readonly(*delegate, true));
+        }
+
+        let mut data = vec![signing_authority_bump];
+        data.extend(discriminator);
+        data.extend(ix.pack());
+
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data,
+        }
+    }
+
+    /// Wraps DEX accounts and instruction into an instruction of the proxy
+    /// which, unlike `proxy`, carries no credix accounts. The market cranks
+    /// don't touch LP tokens.
+    fn relay(&self, dex_accounts: Vec<AccountMeta>, ix: MarketInstruction) -> Instruction {
+        let (_, signing_authority_bump) = find_permissioned_signing_authority(&self.program_id);
+        let mut accounts = vec![AccountMeta::new_readonly(self.dex_program_id, false)];
+        accounts.extend(dex_accounts);
+
+        let mut data = vec![signing_authority_bump];
+        data.extend(Self::discriminator(0, None));
+        data.extend(ix.pack());
+
+        Instruction {
+            program_id: self.program_id,
+            accounts,
+            data,
+        }
+    }
+
+    fn instruction(
+        &self,
+        accounts: impl ToAccountMetas,
+        data: impl InstructionData,
+    ) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_spl::{associated_token, token};
+use credix_client::*;
+use serum_dex_permissioned::serum_dex::instruction::{
+    self as dex, CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior,
+};
+use serum_dex_permissioned::serum_dex::matching::{OrderType, Side};
+use serum_dex_permissioned::{
+    open_orders_authority, open_orders_init_authority, DryRun, TRADING_DELEGATE_SEED,
+};
+use std::num::NonZeroU64;
+
+// Accounts in front of the DEX accounts of a proxied instruction: the DEX
+// program and the credix accounts, then the allowlist accounts for
+// `init_open_orders` and `new_order_v3`.
+const CREDIX_HEADER: usize = 1 + 13;
+const ALLOWLIST_HEADER: usize = CREDIX_HEADER + 2;
+
+fn market() -> PermissionedMarket {
+    let credix = CredixMarket::new(
+        "credix-market",
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let accounts = MarketAccounts {
+        market: Pubkey::new_unique(),
+        request_queue: Pubkey::new_unique(),
+        event_queue: Pubkey::new_unique(),
+        bids: Pubkey::new_unique(),
+        asks: Pubkey::new_unique(),
+        coin_vault: Pubkey::new_unique(),
+        pc_vault: Pubkey::new_unique(),
+        vault_signer: Pubkey::new_unique(),
+    };
+    PermissionedMarket::new(credix, credix_permissioned_market::dex::ID, accounts)
+}
+
+// Runs the middleware of the proxy over the request, with accounts holding
+// no data.
+fn dry_run(ix: &Instruction) -> DryRun {
+    let mut storage: Vec<(Pubkey, u64, Vec<u8>, Pubkey)> = ix
+        .accounts
+        .iter()
+        .map(|meta| (meta.pubkey, 0, Vec::new(), system_program::ID))
+        .collect();
+    let accounts: Vec<AccountInfo> = storage
+        .iter_mut()
+        .zip(&ix.accounts)
+        .map(|((key, lamports, data, owner), meta)| {
+            AccountInfo::new(
+                key,
+                meta.is_signer,
+                meta.is_writable,
+                lamports,
+                data,
+                owner,
+                false,
+                0,
+            )
+        })
+        .collect();
+    credix_permissioned_market::dry_run(&ix.program_id, &accounts, &ix.data)
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn credix_accounts_follow_the_permissioned_market_layout() {
+    let market = market();
+    let credix = &market.credix;
+    let owner = Pubkey::new_unique();
+    let ix = market.cancel_order_by_client_id_v2(&owner, 0, None, 1);
+
+    let keys: Vec<Pubkey> = ix.accounts[..CREDIX_HEADER]
+        .iter()
+        .map(|meta| meta.pubkey)
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            market.dex_program_id,
+            owner,
+            associated_token::get_associated_token_address(&owner, &credix.lp_token_mint),
+            find_permissioned_signing_authority(&credix_permissioned_market::ID).0,
+            find_signing_authority(&credix::ID, &credix.global_market_state).0,
+            credix.lp_token_mint,
+            find_global_market_state(&credix::ID, "credix-market").0,
+            find_credix_pass(&credix::ID, &credix.global_market_state, &owner).0,
+            token::ID,
+            credix::ID,
+            associated_token::ID,
+            system_program::ID,
+            sysvar::rent::ID,
+            solana_gateway::state::get_gateway_token_address_with_seed(
+                &owner,
+                &None,
+                &credix.gatekeeper_network
+            )
+            .0,
+        ]
+    );
+    assert!(ix.accounts[1].is_signer);
+    assert!(ix.accounts[2].is_writable);
+}
+
+#[test]
+fn proxied_instructions_carry_the_serum_accounts() {
+    let market = market();
+    let accounts = market.accounts;
+    let dex_program_id = market.dex_program_id;
+    let owner = Pubkey::new_unique();
+    let open_orders = market.open_orders(&owner, 1);
+    let (payer, wallet, referral) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+
+    let init_authority =
+        find_open_orders_init_authority(&market.program_id, &dex_program_id, &accounts.market).0;
+    let ix = market.init_open_orders(&owner, 1);
+    let serum = dex::init_open_orders(
+        &dex_program_id,
+        &open_orders,
+        &owner,
+        &accounts.market,
+        Some(&init_authority),
+    )
+    .unwrap();
+    // The DEX program and system program precede the accounts of serum.
+    assert_eq!(
+        ix.accounts[ALLOWLIST_HEADER + 2..].to_vec(),
+        serum
+            .accounts
+            .into_iter()
+            .map(|meta| AccountMeta {
+                // The owner pays for the open orders account, created by the
+                // proxy which signs for the init authority.
+                is_signer: meta.pubkey == owner,
+                is_writable: meta.is_writable || meta.pubkey == owner,
+                ..meta
+            })
+            .collect::<Vec<_>>()
+    );
+
+    let order = NewOrderInstructionV3 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(1_000).unwrap(),
+        max_coin_qty: NonZeroU64::new(10).unwrap(),
+        max_native_pc_qty_including_fees: NonZeroU64::new(10_000).unwrap(),
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        order_type: OrderType::Limit,
+        client_order_id: 1,
+        limit: 10,
+    };
+    let ix = market.new_order_v3(&owner, 1, &payer, None, order.clone());
+    let serum = dex::new_order(
+        &accounts.market,
+        &open_orders,
+        &accounts.request_queue,
+        &accounts.event_queue,
+        &accounts.bids,
+        &accounts.asks,
+        &payer,
+        &owner,
+        &accounts.coin_vault,
+        &accounts.pc_vault,
+        &token::ID,
+        &sysvar::rent::ID,
+        None,
+        &dex_program_id,
+        order.side,
+        order.limit_price,
+        order.max_coin_qty,
+        order.order_type,
+        order.client_order_id,
+        order.self_trade_behavior,
+        order.limit,
+        order.max_native_pc_qty_including_fees,
+    )
+    .unwrap();
+    assert_eq!(ix.accounts[ALLOWLIST_HEADER..].to_vec(), serum.accounts);
+
+    let ix = market.cancel_order_v2(
+        &owner,
+        1,
+        None,
+        CancelOrderInstructionV2 {
+            side: Side::Bid,
+            order_id: 7,
+        },
+    );
+    let serum = dex::cancel_order(
+        &dex_program_id,
+        &accounts.market,
+        &accounts.bids,
+        &accounts.asks,
+        &open_orders,
+        &owner,
+        &accounts.event_queue,
+        Side::Bid,
+        7,
+    )
+    .unwrap();
+    assert_eq!(ix.accounts[CREDIX_HEADER..].to_vec(), serum.accounts);
+
+    let ix = market.settle_funds(&owner, 1, &wallet, &payer, &referral);
+    let serum = dex::settle_funds(
+        &dex_program_id,
+        &accounts.market,
+        &token::ID,
+        &open_orders,
+        &owner,
+        &accounts.coin_vault,
+        &wallet,
+        &accounts.pc_vault,
+        &payer,
+        Some(&referral),
+        &accounts.vault_signer,
+    )
+    .unwrap();
+    assert_eq!(ix.accounts[CREDIX_HEADER..].to_vec(), serum.accounts);
+
+    let ix = market.close_open_orders(&owner, 1, &wallet);
+    let serum = dex::close_open_orders(
+        &dex_program_id,
+        &open_orders,
+        &owner,
+        &wallet,
+        &accounts.market,
+    )
+    .unwrap();
+    assert_eq!(ix.accounts[CREDIX_HEADER..].to_vec(), serum.accounts);
+}
+
+#[test]
+fn delegated_instructions_end_with_the_trading_delegate() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let delegate = Pubkey::new_unique();
+    let ix = market.cancel_order_by_client_id_v2(&owner, 2, Some(&delegate), 1);
+
+    let accounts = &ix.accounts;
+    assert!(!accounts[1].is_signer);
+    assert!(!accounts[CREDIX_HEADER + 4].is_signer);
+    assert_eq!(
+        accounts[accounts.len() - 2].pubkey,
+        find_trading_delegate(&market.program_id, &owner).0
+    );
+    assert_eq!(
+        accounts[accounts.len() - 1],
+        AccountMeta::new_readonly(delegate, true)
+    );
+    // Signing bump, then the delegated discriminator and the index.
+    assert_eq!(
+        ix.data[..3],
+        [
+            find_permissioned_signing_authority(&market.program_id).1,
+            4,
+            2
+        ]
+    );
+}
+
+#[test]
+fn proxy_relays_cancels_and_cranks_to_the_dex() {
+    let market = market();
+    let accounts = market.accounts;
+    let dex_program_id = market.dex_program_id;
+    let owner = Pubkey::new_unique();
+    let open_orders = market.open_orders(&owner, 0);
+
+    // The open orders account signs for its owner, keeping its own
+    // writability.
+    let ix = market.cancel_order_by_client_id_v2(&owner, 0, None, 3);
+    let dry_run = dry_run(&ix);
+    let mut serum = dex::cancel_order_by_client_order_id(
+        &dex_program_id,
+        &accounts.market,
+        &accounts.bids,
+        &accounts.asks,
+        &open_orders,
+        &open_orders,
+        &accounts.event_queue,
+        3,
+    )
+    .unwrap();
+    serum.accounts[4].is_writable = true;
+    assert_eq!(dry_run.instruction, serum);
+
+    let open_orders = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let (coin_fee, pc_fee) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let ix = market.consume_events(&open_orders, &coin_fee, &pc_fee, 5);
+    let serum = dex::consume_events(
+        &dex_program_id,
+        open_orders.iter().collect(),
+        &accounts.market,
+        &accounts.event_queue,
+        &coin_fee,
+        &pc_fee,
+        5,
+    )
+    .unwrap();
+    assert_eq!(dry_run_instruction(&ix), serum);
+
+    let authority = Pubkey::new_unique();
+    let ix = market.consume_events_permissioned(&open_orders, &authority, 5);
+    let serum = dex::consume_events_permissioned(
+        &dex_program_id,
+        open_orders.iter().collect(),
+        &accounts.market,
+        &accounts.event_queue,
+        &authority,
+        5,
+    )
+    .unwrap();
+    assert_eq!(dry_run_instruction(&ix), serum);
+
+    // Pruned open orders are their own owner.
+    let open_orders = market.open_orders(&owner, 1);
+    let ix = market.prune(&authority, &owner, 1, 5);
+    let mut serum = dex::prune(
+        &dex_program_id,
+        &accounts.market,
+        &accounts.bids,
+        &accounts.asks,
+        &authority,
+        &open_orders,
+        &open_orders,
+        &accounts.event_queue,
+        5,
+    )
+    .unwrap();
+    serum.accounts[5].is_writable = true;
+    assert_eq!(dry_run_instruction(&ix), serum);
+}
+
+fn dry_run_instruction(ix: &Instruction) -> Instruction {
+    let dry_run = dry_run(ix);
+    assert!(dry_run.pre_instructions.is_empty() && dry_run.post_instructions.is_empty());
+    dry_run.instruction
+}
+
+#[test]
+fn pdas_match_the_on_chain_seeds() {
+    let market = market();
+    let (program_id, dex_program_id) = (market.program_id, market.dex_program_id);
+    let market_key = market.accounts.market;
+    let owner = Pubkey::new_unique();
+    let address = |seeds: Vec<Vec<u8>>, program_id: &Pubkey| {
+        let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+        Pubkey::create_program_address(&seeds, program_id).unwrap()
+    };
+
+    for index in [0, 1, 255] {
+        let (open_orders, bump) =
+            find_open_orders(&program_id, &dex_program_id, &market_key, &owner, index);
+        let seeds = open_orders_authority! {
+            program = &program_id,
+            dex_program = dex_program_id,
+            market = market_key,
+            authority = owner,
+            index = index,
+            bump = bump
+        };
+        assert_eq!(address(seeds, &program_id), open_orders);
+    }
+
+    let (init_authority, bump) =
+        find_open_orders_init_authority(&program_id, &dex_program_id, &market_key);
+    let seeds = open_orders_init_authority! {
+        program = &program_id,
+        dex_program = dex_program_id,
+        market = market_key,
+        bump = bump
+    };
+    assert_eq!(address(seeds, &program_id), init_authority);
+
+    // Seeds of the accounts contexts of both programs.
+    let (signing_authority, bump) = find_permissioned_signing_authority(&program_id);
+    let seeds = vec![b"signing-authority".to_vec(), vec![bump]];
+    assert_eq!(address(seeds, &program_id), signing_authority);
+
+    let (trading_delegate, bump) = find_trading_delegate(&program_id, &owner);
+    let seeds = vec![
+        TRADING_DELEGATE_SEED.to_vec(),
+        owner.to_bytes().to_vec(),
+        vec![bump],
+    ];
+    assert_eq!(address(seeds, &program_id), trading_delegate);
+
+    let credix = &market.credix;
+    let gms = credix.global_market_state;
+    let (credix_pass, bump) = find_credix_pass(&credix::ID, &gms, &owner);
+    let seeds = vec![
+        gms.to_bytes().to_vec(),
+        owner.to_bytes().to_vec(),
+        credix::CREDIX_PASS_SEED.as_bytes().to_vec(),
+        vec![bump],
+    ];
+    assert_eq!(address(seeds, &credix::ID), credix_pass);
+    assert_eq!(credix.credix_pass(&owner), credix_pass);
+
+    let (withdraw_request, bump) = find_withdraw_request(&credix::ID, &gms, &owner);
+    let seeds = vec![
+        gms.to_bytes().to_vec(),
+        owner.to_bytes().to_vec(),
+        credix::WITHDRAW_REQUEST_SEED.as_bytes().to_vec(),
+        vec![bump],
+    ];
+    assert_eq!(address(seeds, &credix::ID), withdraw_request);
+}
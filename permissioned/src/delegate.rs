@@ -0,0 +1,70 @@
+use crate::ErrorCode;
+use anchor_lang::prelude::*;
+
+/// Seed of the trading delegate PDA of an open orders owner.
+pub const TRADING_DELEGATE_SEED: &[u8] = b"trading-delegate";
+
+/// Permission to place new orders on behalf of the owner.
+pub const PLACE_ORDERS: u8 = 1 << 0;
+/// Permission to cancel orders on behalf of the owner.
+pub const CANCEL_ORDERS: u8 = 1 << 1;
+
+/// Lets a hot key trade the open orders accounts of their owner.
+///
+/// A delegate can only place and cancel orders. Settling funds, closing and
+/// initializing open orders accounts always require the owner's signature.
+///
+/// The record is an anchor account of the proxy program using this crate.
+/// Its discriminator is the one of `#[account]`, whose namespace is spelled
+/// out to leave the owner to the proxy program.
+#[account("account")]
+#[derive(Copy, Debug, Default, PartialEq)]
+pub struct TradingDelegate {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub permissions: u8,
+    // Unix timestamp at which the delegation lapses, 0 for no expiry.
+    pub expiry: i64,
+    pub bump: u8,
+}
+
+impl TradingDelegate {
+    /// Size of the account, including the discriminator.
+    pub const LEN: usize = 8 + 32 + 32 + 1 + 8 + 1;
+
+    /// Deserializes the record, which must be owned by the proxy program.
+    pub fn load(
+        account: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> std::result::Result<Self, ProgramError> {
+        require!(account.owner == program_id, ErrorCode::InvalidDelegate);
+        let data = account.try_borrow_data()?;
+        Self::try_deserialize(&mut data.as_ref()).map_err(|_| ErrorCode::InvalidDelegate.into())
+    }
+
+    pub fn store(&self, account: &AccountInfo) -> ProgramResult {
+        let mut data = account.try_borrow_mut_data()?;
+        self.try_serialize(&mut data.as_mut())
+            .map_err(|_| ProgramError::AccountDataTooSmall)
+    }
+
+    /// Closes the record, sending its rent to the given account.
+    pub fn close(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+        let lamports = account.lamports();
+        **account.try_borrow_mut_lamports()? = 0;
+        **destination.try_borrow_mut_lamports()? = destination
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ErrorCode::Overflow)?;
+        account.try_borrow_mut_data()?.fill(0);
+        Ok(())
+    }
+
+    /// Returns whether `delegate` may trade with the given permission at
+    /// the given time.
+    pub fn authorizes(&self, delegate: &Pubkey, permission: u8, now: i64) -> bool {
+        &self.delegate == delegate
+            && self.permissions & permission == permission
+            && (self.expiry == 0 || now < self.expiry)
+    }
+}
@@ -1,6 +1,12 @@
+mod delegate;
+mod market;
 mod middleware;
 mod proxy;
+#[cfg(feature = "testing")]
+pub mod testing;
 
+pub use delegate::*;
+pub use market::*;
 pub use middleware::*;
 pub use proxy::*;
 pub use serum_dex;
@@ -0,0 +1,109 @@
+use crate::ErrorCode;
+use anchor_lang::prelude::*;
+use serum_dex::state::{AccountFlag, MarketState, ACCOUNT_HEAD_PADDING, ACCOUNT_TAIL_PADDING};
+use std::cell::Ref;
+use std::mem::size_of;
+
+/// Read only, zero-copy view over a Serum market account.
+///
+/// Loading the view checks that the account is owned by the given DEX
+/// program, carries the Serum padding and is flagged as an initialized market.
+pub struct MarketView<'a> {
+    state: Ref<'a, MarketState>,
+}
+
+impl<'a> MarketView<'a> {
+    pub fn load(
+        market: &'a AccountInfo,
+        dex_program_id: &Pubkey,
+    ) -> std::result::Result<Self, ProgramError> {
+        require!(market.owner == dex_program_id, ErrorCode::InvalidDexPid);
+
+        let data = market.try_borrow_data()?;
+        let head = ACCOUNT_HEAD_PADDING.len();
+        let tail = ACCOUNT_TAIL_PADDING.len();
+        require!(
+            data.len() >= head + size_of::<MarketState>() + tail
+                && &data[..head] == ACCOUNT_HEAD_PADDING
+                && &data[data.len() - tail..] == ACCOUNT_TAIL_PADDING,
+            ErrorCode::InvalidMarket
+        );
+
+        let view = Self {
+            state: Ref::map(data, |data| {
+                bytemuck::from_bytes(&data[head..head + size_of::<MarketState>()])
+            }),
+        };
+        require!(
+            view.has_flag(AccountFlag::Initialized) && view.has_flag(AccountFlag::Market),
+            ErrorCode::InvalidMarket
+        );
+        Ok(view)
+    }
+
+    pub fn account_flags(&self) -> u64 {
+        self.state.account_flags
+    }
+
+    pub fn has_flag(&self, flag: AccountFlag) -> bool {
+        self.account_flags() & flag as u64 != 0
+    }
+
+    pub fn own_address(&self) -> Pubkey {
+        to_pubkey(self.state.own_address)
+    }
+
+    pub fn coin_mint(&self) -> Pubkey {
+        to_pubkey(self.state.coin_mint)
+    }
+
+    pub fn pc_mint(&self) -> Pubkey {
+        to_pubkey(self.state.pc_mint)
+    }
+
+    pub fn coin_vault(&self) -> Pubkey {
+        to_pubkey(self.state.coin_vault)
+    }
+
+    pub fn pc_vault(&self) -> Pubkey {
+        to_pubkey(self.state.pc_vault)
+    }
+
+    pub fn request_queue(&self) -> Pubkey {
+        to_pubkey(self.state.req_q)
+    }
+
+    pub fn event_queue(&self) -> Pubkey {
+        to_pubkey(self.state.event_q)
+    }
+
+    pub fn bids(&self) -> Pubkey {
+        to_pubkey(self.state.bids)
+    }
+
+    pub fn asks(&self) -> Pubkey {
+        to_pubkey(self.state.asks)
+    }
+
+    pub fn coin_lot_size(&self) -> u64 {
+        self.state.coin_lot_size
+    }
+
+    pub fn pc_lot_size(&self) -> u64 {
+        self.state.pc_lot_size
+    }
+
+    pub fn vault_signer_nonce(&self) -> u64 {
+        self.state.vault_signer_nonce
+    }
+
+    /// Converts an amount of coin lots into native coin tokens.
+    pub fn coin_lots_to_native(&self, lots: u64) -> std::result::Result<u64, ProgramError> {
+        lots.checked_mul(self.coin_lot_size())
+            .ok_or_else(|| ErrorCode::Overflow.into())
+    }
+}
+
+fn to_pubkey(words: [u64; 4]) -> Pubkey {
+    Pubkey::new_from_array(bytemuck::cast(words))
+}
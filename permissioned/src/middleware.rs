@@ -1,12 +1,18 @@
-use crate::{open_orders_authority, open_orders_init_authority};
+use crate::{
+    open_orders_authority, open_orders_init_authority, MarketView, TradingDelegate, CANCEL_ORDERS,
+    PLACE_ORDERS,
+};
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
 use anchor_lang::solana_program::system_program;
 use anchor_lang::Accounts;
 use anchor_spl::token;
 use serum_dex::instruction::*;
 use serum_dex::matching::Side;
 use serum_dex::state::OpenOrders;
+use solana_gateway::{Gateway, VerificationOptions};
 use std::mem::size_of;
 
 /// Per request context. Can be used to share data between middleware handlers.
@@ -33,7 +39,7 @@ type PostCallback<'a, 'info> = fn(
     Vec<u8>,
 ) -> ProgramResult;
 
-type Seeds = Vec<Vec<Vec<u8>>>;
+pub type Seeds = Vec<Vec<Vec<u8>>>;
 
 impl<'a, 'info> Context<'a, 'info> {
     pub fn new(
@@ -117,10 +123,19 @@ pub trait MarketMiddleware {
 
 /// Checks that the given open orders account signs the transaction and then
 /// replaces it with the open orders account, which must be a PDA.
+///
+/// A user can own several open orders accounts on the same market, told apart
+/// by an index. The open orders account with index 0 keeps the original PDA
+/// derivation.
+///
+/// Orders can also be placed and cancelled by a `TradingDelegate` of the
+/// owner, in which case the owner doesn't sign.
 #[derive(Default)]
 pub struct OpenOrdersPda {
     bump: u8,
     bump_init: u8,
+    index: u8,
+    delegated: bool,
 }
 
 impl OpenOrdersPda {
@@ -128,8 +143,55 @@ impl OpenOrdersPda {
         Self {
             bump: 0,
             bump_init: 0,
+            index: 0,
+            delegated: false,
         }
     }
+
+    /// Checks that the owner of the open orders account signs the
+    /// transaction or, for delegated instructions, a trading delegate of the
+    /// owner with the given permission. A delegate never stands in for the
+    /// owner when no permission is given.
+    ///
+    /// Delegated instructions carry two extra accounts after the DEX
+    /// accounts, which are removed here:
+    ///
+    /// 0. `[]` trading delegate record of the owner.
+    /// 1. `[signer]` delegate.
+    fn authorize(
+        &self,
+        ctx: &mut Context,
+        user_idx: usize,
+        permission: Option<u8>,
+    ) -> ProgramResult {
+        if !self.delegated {
+            require!(
+                ctx.accounts[user_idx].is_signer,
+                ErrorCode::UnauthorizedUser
+            );
+            return Ok(());
+        }
+
+        let permission = permission.ok_or(ErrorCode::UnauthorizedUser)?;
+        require!(
+            ctx.accounts.len() >= user_idx + 3,
+            ErrorCode::NotEnoughAccounts
+        );
+        let delegate = ctx.accounts.pop().unwrap();
+        let trading_delegate = ctx.accounts.pop().unwrap();
+        require!(delegate.is_signer, ErrorCode::UnauthorizedUser);
+
+        let trading_delegate = TradingDelegate::load(&trading_delegate, ctx.program_id)?;
+        require!(
+            &trading_delegate.owner == ctx.accounts[user_idx].key,
+            ErrorCode::InvalidDelegate
+        );
+        require!(
+            trading_delegate.authorizes(delegate.key, permission, Clock::get()?.unix_timestamp),
+            ErrorCode::UnauthorizedDelegate
+        );
+        Ok(())
+    }
     fn prepare_pda<'info>(acc_info: &AccountInfo<'info>) -> AccountInfo<'info> {
         let mut acc_info = acc_info.clone();
         acc_info.is_signer = true;
@@ -143,11 +205,32 @@ impl MarketMiddleware for OpenOrdersPda {
         let disc = data[0];
         *data = &data[1..];
 
-        // Discriminator == 0 implies it's the init instruction.
-        if disc == 0 {
-            self.bump = data[0];
-            self.bump_init = data[1];
-            *data = &data[2..];
+        // Discriminator == 0 implies it's the init instruction, discriminators
+        // 2 and 3 are the init and regular instructions for an open orders
+        // account with a non zero index. Discriminator 4 marks an instruction
+        // signed by a trading delegate.
+        match disc {
+            0 => {
+                self.bump = data[0];
+                self.bump_init = data[1];
+                *data = &data[2..];
+            }
+            2 => {
+                self.bump = data[0];
+                self.bump_init = data[1];
+                self.index = data[2];
+                *data = &data[3..];
+            }
+            3 => {
+                self.index = data[0];
+                *data = &data[1..];
+            }
+            4 => {
+                self.index = data[0];
+                self.delegated = true;
+                *data = &data[1..];
+            }
+            _ => {}
         }
         Ok(())
     }
@@ -162,15 +245,26 @@ impl MarketMiddleware for OpenOrdersPda {
     ///
     /// 0.   Discriminant.
     /// 1..2 Borsh(struct { bump: u8, bump_init: u8 }).
+    /// 3.   Open orders index, only for discriminant 2.
     /// ..
     fn init_open_orders<'a, 'info>(&self, ctx: &mut Context<'a, 'info>) -> ProgramResult {
+        self.authorize(ctx, 3, None)?;
+        // The open orders account is created for the DEX the request goes to.
+        require!(
+            ctx.accounts[0].key == ctx.dex_program_id,
+            ErrorCode::InvalidTargetProgram
+        );
         let market = &ctx.accounts[4];
         let user = &ctx.accounts[3];
 
         // Initialize PDA.
         let mut accounts = &ctx.accounts[..];
 
-        InitAccount::try_accounts(ctx.program_id, &mut accounts, &[self.bump, self.bump_init])?;
+        InitAccount::try_accounts(
+            ctx.program_id,
+            &mut accounts,
+            &[self.bump, self.bump_init, self.index],
+        )?;
 
         // Add signer to context.
         ctx.seeds.push(open_orders_authority! {
@@ -178,6 +272,7 @@ impl MarketMiddleware for OpenOrdersPda {
             dex_program = ctx.dex_program_id,
             market = market.key,
             authority = user.key,
+            index = self.index,
             bump = self.bump
         });
         ctx.seeds.push(open_orders_init_authority! {
@@ -205,67 +300,95 @@ impl MarketMiddleware for OpenOrdersPda {
     ///
     /// 0.   Discriminant.
     /// ..
+    ///
+    /// When a trading delegate places the order, the owner must have approved
+    /// the open orders account as delegate of the payer token account
+    /// beforehand, since the owner doesn't sign. Orders of the owner restore
+    /// that approval once placed.
     fn new_order_v3(&self, ctx: &mut Context, ix: &mut NewOrderInstructionV3) -> ProgramResult {
         // The user must authorize the tx.
-        let user = &ctx.accounts[7];
-        if !user.is_signer {
-            return Err(ErrorCode::UnauthorizedUser.into());
-        }
+        self.authorize(ctx, 7, Some(PLACE_ORDERS))?;
 
         let market = &ctx.accounts[0];
         let open_orders = &ctx.accounts[1];
+        let user = &ctx.accounts[7];
         let token_account_payer = &ctx.accounts[6];
 
         // Pre: Give the PDA delegate access.
-        let pre_instruction = {
-            let amount = match ix.side {
-                Side::Bid => ix.max_native_pc_qty_including_fees.get(),
-                Side::Ask => {
-                    // +5 for padding.
-                    let coin_lot_idx = 5 + 43 * 8;
-                    let data = market.try_borrow_data()?;
-                    let mut coin_lot_array = [0u8; 8];
-                    coin_lot_array.copy_from_slice(&data[coin_lot_idx..coin_lot_idx + 8]);
-                    let coin_lot_size = u64::from_le_bytes(coin_lot_array);
-                    ix.max_coin_qty.get().checked_mul(coin_lot_size).unwrap()
+        if !self.delegated {
+            let pre_instruction = {
+                let amount = match ix.side {
+                    Side::Bid => ix.max_native_pc_qty_including_fees.get(),
+                    Side::Ask => MarketView::load(market, ctx.dex_program_id)?
+                        .coin_lots_to_native(ix.max_coin_qty.get())?,
+                };
+                let ix = spl_token::instruction::approve(
+                    &spl_token::ID,
+                    token_account_payer.key,
+                    open_orders.key,
+                    user.key,
+                    &[],
+                    amount,
+                )?;
+                let accounts = vec![
+                    token_account_payer.clone(),
+                    open_orders.clone(),
+                    user.clone(),
+                ];
+                (ix, accounts, Vec::new())
+            };
+            ctx.pre_instructions.push(pre_instruction);
+
+            // Post: Revoke the PDA's delegate access, or restore the standing
+            // approval the orders of trading delegates are paid with.
+            let standing_approval = {
+                let payer =
+                    spl_token::state::Account::unpack(&token_account_payer.try_borrow_data()?)?;
+                match payer.delegate {
+                    COption::Some(delegate) if &delegate == open_orders.key => {
+                        Some(payer.delegated_amount)
+                    }
+                    _ => None,
                 }
             };
-            let ix = spl_token::instruction::approve(
-                &spl_token::ID,
-                token_account_payer.key,
-                open_orders.key,
-                user.key,
-                &[],
-                amount,
-            )?;
-            let accounts = vec![
-                token_account_payer.clone(),
-                open_orders.clone(),
-                user.clone(),
-            ];
-            (ix, accounts, Vec::new())
-        };
-        ctx.pre_instructions.push(pre_instruction);
-
-        // Post: Revoke the PDA's delegate access.
-        let post_instruction = {
-            let ix = spl_token::instruction::revoke(
-                &spl_token::ID,
-                token_account_payer.key,
-                user.key,
-                &[],
-            )?;
-            let accounts = vec![token_account_payer.clone(), user.clone()];
-            (ix, accounts, Vec::new())
-        };
-        ctx.post_instructions.push(post_instruction);
+            let post_instruction = match standing_approval {
+                Some(amount) => {
+                    let ix = spl_token::instruction::approve(
+                        &spl_token::ID,
+                        token_account_payer.key,
+                        open_orders.key,
+                        user.key,
+                        &[],
+                        amount,
+                    )?;
+                    let accounts = vec![
+                        token_account_payer.clone(),
+                        open_orders.clone(),
+                        user.clone(),
+                    ];
+                    (ix, accounts, Vec::new())
+                }
+                None => {
+                    let ix = spl_token::instruction::revoke(
+                        &spl_token::ID,
+                        token_account_payer.key,
+                        user.key,
+                        &[],
+                    )?;
+                    let accounts = vec![token_account_payer.clone(), user.clone()];
+                    (ix, accounts, Vec::new())
+                }
+            };
+            ctx.post_instructions.push(post_instruction);
+        }
 
         // Proxy: PDA must sign the new order.
         ctx.seeds.push(open_orders_authority! {
             program = ctx.program_id,
             dex_program = ctx.dex_program_id,
             market = market.key,
-            authority = user.key
+            authority = user.key,
+            index = self.index
         });
         ctx.accounts[7] = Self::prepare_pda(open_orders);
 
@@ -285,17 +408,16 @@ impl MarketMiddleware for OpenOrdersPda {
         ctx: &mut Context,
         _ix: &mut CancelOrderInstructionV2,
     ) -> ProgramResult {
+        self.authorize(ctx, 4, Some(CANCEL_ORDERS))?;
         let market = &ctx.accounts[0];
         let user = &ctx.accounts[4];
-        if !user.is_signer {
-            return Err(ErrorCode::UnauthorizedUser.into());
-        }
 
         ctx.seeds.push(open_orders_authority! {
             program = ctx.program_id,
             dex_program = ctx.dex_program_id,
             market = market.key,
-            authority = user.key
+            authority = user.key,
+            index = self.index
         });
 
         ctx.accounts[4] = Self::prepare_pda(&ctx.accounts[3]);
@@ -316,17 +438,16 @@ impl MarketMiddleware for OpenOrdersPda {
         ctx: &mut Context,
         _client_id: &mut u64,
     ) -> ProgramResult {
+        self.authorize(ctx, 4, Some(CANCEL_ORDERS))?;
         let market = &ctx.accounts[0];
         let user = &ctx.accounts[4];
-        if !user.is_signer {
-            return Err(ErrorCode::UnauthorizedUser.into());
-        }
 
         ctx.seeds.push(open_orders_authority! {
             program = ctx.program_id,
             dex_program = ctx.dex_program_id,
             market = market.key,
-            authority = user.key
+            authority = user.key,
+            index = self.index
         });
 
         ctx.accounts[4] = Self::prepare_pda(&ctx.accounts[3]);
@@ -343,17 +464,16 @@ impl MarketMiddleware for OpenOrdersPda {
     /// 0.   Discriminant.
     /// ..
     fn settle_funds(&self, ctx: &mut Context) -> ProgramResult {
+        self.authorize(ctx, 2, None)?;
         let market = &ctx.accounts[0];
         let user = &ctx.accounts[2];
-        if !user.is_signer {
-            return Err(ErrorCode::UnauthorizedUser.into());
-        }
 
         ctx.seeds.push(open_orders_authority! {
             program = ctx.program_id,
             dex_program = ctx.dex_program_id,
             market = market.key,
-            authority = user.key
+            authority = user.key,
+            index = self.index
         });
 
         ctx.accounts[2] = Self::prepare_pda(&ctx.accounts[1]);
@@ -370,17 +490,16 @@ impl MarketMiddleware for OpenOrdersPda {
     /// 0.   Discriminant.
     /// ..
     fn close_open_orders(&self, ctx: &mut Context) -> ProgramResult {
+        self.authorize(ctx, 1, None)?;
         let market = &ctx.accounts[3];
         let user = &ctx.accounts[1];
-        if !user.is_signer {
-            return Err(ErrorCode::UnauthorizedUser.into());
-        }
 
         ctx.seeds.push(open_orders_authority! {
             program = ctx.program_id,
             dex_program = ctx.dex_program_id,
             market = market.key,
-            authority = user.key
+            authority = user.key,
+            index = self.index
         });
 
         ctx.accounts[1] = Self::prepare_pda(&ctx.accounts[0]);
@@ -472,9 +591,179 @@ impl MarketMiddleware for ReferralFees {
     }
 }
 
+/// Rejects new orders whose limit price lies outside of a band around a
+/// reference price, and all new orders while trading is halted.
+///
+/// Cancels, settlements and every other instruction pass through untouched,
+/// so that users can always unwind their positions during a halt.
+pub struct PriceBand {
+    reference_price: Option<ReferencePrice>,
+    band_bps: u16,
+    halted: bool,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+}
+
+/// Price of the coin, expressed as an amount of native pc tokens for an
+/// amount of native coin tokens.
+#[derive(Clone, Copy, Debug)]
+pub struct ReferencePrice {
+    pub native_pc: u64,
+    pub native_coin: u64,
+}
+
+impl PriceBand {
+    /// When no reference price is known, only the halt flag is enforced.
+    ///
+    /// The market is resolved by the caller, as middleware running before
+    /// this one may have sliced off the accounts in front of it.
+    pub fn new(
+        market: &MarketView,
+        reference_price: Option<ReferencePrice>,
+        band_bps: u16,
+        halted: bool,
+    ) -> Self {
+        Self {
+            reference_price,
+            band_bps,
+            halted,
+            coin_lot_size: market.coin_lot_size(),
+            pc_lot_size: market.pc_lot_size(),
+        }
+    }
+}
+
+impl MarketMiddleware for PriceBand {
+    fn new_order_v3(&self, _ctx: &mut Context, ix: &mut NewOrderInstructionV3) -> ProgramResult {
+        require!(!self.halted, ErrorCode::TradingHalted);
+
+        let reference_price = match self.reference_price {
+            Some(reference_price) if reference_price.native_coin != 0 => reference_price,
+            _ => return Ok(()),
+        };
+
+        // Limit prices are denominated in pc lots per coin lot.
+        let reference_lots = (reference_price.native_pc as u128 * self.coin_lot_size as u128)
+            / (reference_price.native_coin as u128 * self.pc_lot_size.max(1) as u128);
+
+        let band_bps = BPS_DENOMINATOR.min(self.band_bps as u128);
+        let lower = reference_lots.saturating_mul(BPS_DENOMINATOR - band_bps) / BPS_DENOMINATOR;
+        let upper = reference_lots.saturating_mul(BPS_DENOMINATOR + band_bps) / BPS_DENOMINATOR;
+        let limit_price = ix.limit_price.get() as u128;
+        require!(
+            limit_price >= lower && limit_price <= upper,
+            ErrorCode::PriceOutsideBand
+        );
+
+        Ok(())
+    }
+}
+
+/// Checks that the user holds a valid, unexpired gateway token issued by the
+/// configured gatekeeper network, KYC-gating the market at the proxy layer.
+///
+/// The gateway token of the user is prepended to the accounts of
+/// `init_open_orders`, `new_order_v3` and `settle_funds`. Since the user is
+/// looked up at its DEX account index, this middleware must run before
+/// `OpenOrdersPda` replaces the user with the open orders PDA.
+///
+/// The user signs, except for instructions of a trading delegate, which
+/// `OpenOrdersPda` authorizes instead. The gateway token is the one of the
+/// user, the owner of the open orders account, either way.
+pub struct GatewayCheck {
+    gatekeeper_network: Pubkey,
+    expiry_tolerance_seconds: u32,
+    delegated: bool,
+}
+
+impl GatewayCheck {
+    pub fn new(gatekeeper_network: Pubkey) -> Self {
+        Self {
+            gatekeeper_network,
+            expiry_tolerance_seconds: 0,
+            delegated: false,
+        }
+    }
+
+    /// Builder method accepting gateway tokens up to the given number of
+    /// seconds after they expired.
+    pub fn expiry_tolerance(mut self, seconds: u32) -> Self {
+        self.expiry_tolerance_seconds = seconds;
+        self
+    }
+
+    fn verify(&self, ctx: &mut Context, user_idx: usize) -> ProgramResult {
+        require!(
+            ctx.accounts.len() > 1 + user_idx,
+            ErrorCode::NotEnoughAccounts
+        );
+        let gateway_token = &ctx.accounts[0];
+        let user = &ctx.accounts[1 + user_idx];
+        require!(
+            self.delegated || user.is_signer,
+            ErrorCode::UnauthorizedUser
+        );
+        Gateway::verify_gateway_token_account_info(
+            gateway_token,
+            user.key,
+            &self.gatekeeper_network,
+            Some(VerificationOptions {
+                check_expiry: true,
+                expiry_tolerance_seconds: Some(self.expiry_tolerance_seconds),
+            }),
+        )
+        .map_err(|_| ErrorCode::InvalidGatewayToken)?;
+
+        ctx.accounts = ctx.accounts[1..].to_vec();
+        Ok(())
+    }
+}
+
+impl MarketMiddleware for GatewayCheck {
+    /// Reads the discriminant of `OpenOrdersPda`, which runs after this
+    /// middleware and strips it: 4 marks an instruction signed by a trading
+    /// delegate.
+    fn instruction(&mut self, data: &mut &[u8]) -> ProgramResult {
+        self.delegated = data.first() == Some(&4);
+        Ok(())
+    }
+
+    /// Accounts:
+    ///
+    /// 0. Gateway token.
+    /// 1. Dex program.
+    /// 2. System program.
+    ///
+    /// .. serum_dex::MarketInstruction::InitOpenOrders.
+    fn init_open_orders(&self, ctx: &mut Context) -> ProgramResult {
+        self.verify(ctx, 3)
+    }
+
+    /// Accounts:
+    ///
+    /// 0. Gateway token.
+    ///
+    /// .. serum_dex::MarketInstruction::NewOrderV3.
+    fn new_order_v3(&self, ctx: &mut Context, _ix: &mut NewOrderInstructionV3) -> ProgramResult {
+        self.verify(ctx, 7)
+    }
+
+    /// Accounts:
+    ///
+    /// 0. Gateway token.
+    ///
+    /// .. serum_dex::MarketInstruction::SettleFunds.
+    fn settle_funds(&self, ctx: &mut Context) -> ProgramResult {
+        self.verify(ctx, 2)
+    }
+}
+
 // Macros.
 
 /// Returns the seeds used for a user's open orders account PDA.
+///
+/// The index seed is left out for index 0, keeping the derivation of the
+/// first open orders account of a user backwards compatible.
 #[macro_export]
 macro_rules! open_orders_authority {
     (
@@ -484,13 +773,14 @@ macro_rules! open_orders_authority {
         authority = $authority:expr,
         bump = $bump:expr
     ) => {
-        vec![
-            b"open-orders".to_vec(),
-            $dex_program.as_ref().to_vec(),
-            $market.as_ref().to_vec(),
-            $authority.as_ref().to_vec(),
-            vec![$bump],
-        ]
+        $crate::open_orders_authority! {
+            program = $program,
+            dex_program = $dex_program,
+            market = $market,
+            authority = $authority,
+            index = 0,
+            bump = $bump
+        }
     };
     (
         program = $program:expr,
@@ -498,25 +788,62 @@ macro_rules! open_orders_authority {
         market = $market:expr,
         authority = $authority:expr
     ) => {
-        vec![
+        $crate::open_orders_authority! {
+            program = $program,
+            dex_program = $dex_program,
+            market = $market,
+            authority = $authority,
+            index = 0
+        }
+    };
+    (
+        program = $program:expr,
+        dex_program = $dex_program:expr,
+        market = $market:expr,
+        authority = $authority:expr,
+        index = $index:expr,
+        bump = $bump:expr
+    ) => {{
+        let mut seeds = vec![
             b"open-orders".to_vec(),
             $dex_program.as_ref().to_vec(),
             $market.as_ref().to_vec(),
             $authority.as_ref().to_vec(),
-            vec![
-                Pubkey::find_program_address(
-                    &[
-                        b"open-orders".as_ref(),
-                        $dex_program.as_ref(),
-                        $market.as_ref(),
-                        $authority.as_ref(),
-                    ],
-                    $program,
-                )
-                .1,
+        ];
+        if $index != 0 {
+            seeds.push(vec![$index]);
+        }
+        seeds.push(vec![$bump]);
+        seeds
+    }};
+    (
+        program = $program:expr,
+        dex_program = $dex_program:expr,
+        market = $market:expr,
+        authority = $authority:expr,
+        index = $index:expr
+    ) => {{
+        let index_seed = $crate::open_orders_index_seed($index);
+        let bump = Pubkey::find_program_address(
+            &[
+                b"open-orders".as_ref(),
+                $dex_program.as_ref(),
+                $market.as_ref(),
+                $authority.as_ref(),
+                index_seed.as_ref(),
             ],
-        ]
-    };
+            $program,
+        )
+        .1;
+        $crate::open_orders_authority! {
+            program = $program,
+            dex_program = $dex_program,
+            market = $market,
+            authority = $authority,
+            index = $index,
+            bump = bump
+        }
+    }};
 }
 
 /// Returns the seeds used for the open orders init authority.
@@ -557,21 +884,41 @@ pub enum ErrorCode {
     NotEnoughAccounts,
     #[msg("Invalid target program ID")]
     InvalidTargetProgram,
+    #[msg("Trading is halted on this market")]
+    TradingHalted,
+    #[msg("Limit price is outside of the allowed price band")]
+    PriceOutsideBand,
+    #[msg("Invalid serum market account")]
+    InvalidMarket,
+    #[msg("Arithmetic overflow")]
+    Overflow,
+    #[msg("Gateway token is missing, revoked or expired")]
+    InvalidGatewayToken,
+    #[msg("Invalid trading delegate account")]
+    InvalidDelegate,
+    #[msg("The delegate isn't allowed to perform this action")]
+    UnauthorizedDelegate,
 }
 
 #[derive(Accounts)]
-#[instruction(bump: u8, bump_init: u8)]
+#[instruction(bump: u8, bump_init: u8, index: u8)]
 pub struct InitAccount<'info> {
-    #[account(address = dex::ID)]
+    #[account(executable)]
     pub dex_program: AccountInfo<'info>,
     #[account(address = system_program::ID)]
     pub system_program: AccountInfo<'info>,
     #[account(
         init,
-        seeds = [b"open-orders", dex_program.key.as_ref(), market.key.as_ref(), authority.key.as_ref()],
+        seeds = [
+            b"open-orders",
+            dex_program.key.as_ref(),
+            market.key.as_ref(),
+            authority.key.as_ref(),
+            open_orders_index_seed(index).as_ref(),
+        ],
         bump = bump,
         payer = authority,
-        owner = dex::ID,
+        owner = *dex_program.key,
         space = size_of::<OpenOrders>() + SERUM_PADDING,
     )]
     pub open_orders: AccountInfo<'info>,
@@ -586,6 +933,18 @@ pub struct InitAccount<'info> {
     pub open_orders_init_authority: AccountInfo<'info>,
 }
 
+/// Returns the index seed of an open orders account PDA.
+///
+/// The seed is empty for index 0. An empty seed doesn't contribute to the
+/// derived address, so `InitAccount` can always pass it along.
+pub fn open_orders_index_seed(index: u8) -> Vec<u8> {
+    if index == 0 {
+        Vec::new()
+    } else {
+        vec![index]
+    }
+}
+
 // Constants.
 
 // Padding added to every serum account.
@@ -593,7 +952,5 @@ pub struct InitAccount<'info> {
 // b"serum".len() + b"padding".len().
 const SERUM_PADDING: usize = 12;
 
-mod dex {
-    use anchor_lang::prelude::declare_id;
-    declare_id!("A3KCE92wXZMtGGJT6XYL2KHva58VXvWkhcqfJ6Q5JEia");
-}
\ No newline at end of file
+// Basis points in one whole.
+const BPS_DENOMINATOR: u128 = 10_000;
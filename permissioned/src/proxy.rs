@@ -1,5 +1,6 @@
-use crate::{Context, ErrorCode, MarketMiddleware};
+use crate::{Context, ErrorCode, MarketMiddleware, Seeds};
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
 use anchor_lang::solana_program::program;
 use anchor_lang::solana_program::pubkey::Pubkey;
 use serum_dex::instruction::*;
@@ -12,9 +13,18 @@ use serum_dex::instruction::*;
 /// The only requirement for a middleware is that, when all are done processing,
 /// a valid DEX instruction--accounts and instruction data--must be left to
 /// forward to the orderbook program.
-#[derive(Default)]
+///
+/// Requests are only relayed to one of the allowed DEX programs, by default
+/// Serum v3 and OpenBook v1.
 pub struct MarketProxy<'a> {
     middlewares: Vec<&'a mut dyn MarketMiddleware>,
+    dex_program_ids: Vec<Pubkey>,
+}
+
+impl<'a> Default for MarketProxy<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> MarketProxy<'a> {
@@ -22,9 +32,28 @@ impl<'a> MarketProxy<'a> {
     pub fn new() -> Self {
         Self {
             middlewares: Vec::new(),
+            dex_program_ids: vec![serum_v3::ID, openbook_v1::ID],
         }
     }
 
+    /// Builder method for allowing an additional DEX program, e.g. a devnet
+    /// or localnet deployment.
+    pub fn dex_program(mut self, dex_program_id: Pubkey) -> Self {
+        self.dex_program_ids.push(dex_program_id);
+        self
+    }
+
+    /// Builder method for replacing the allowed DEX programs.
+    pub fn dex_programs(mut self, dex_program_ids: Vec<Pubkey>) -> Self {
+        self.dex_program_ids = dex_program_ids;
+        self
+    }
+
+    /// Whether requests are relayed to the given DEX program.
+    pub fn is_dex_program(&self, program_id: &Pubkey) -> bool {
+        self.dex_program_ids.contains(program_id)
+    }
+
     /// Builder method for adding a middleware to the proxy.
     pub fn middleware(mut self, mw: &'a mut dyn MarketMiddleware) -> Self {
         self.middlewares.push(mw);
@@ -38,11 +67,100 @@ impl<'a> MarketProxy<'a> {
         accounts: &[AccountInfo],
         data: &[u8],
     ) -> ProgramResult {
+        let (ctx, ix_data) = match self.handle(program_id, accounts, data)? {
+            Some(handled) => handled,
+            None => return Ok(()),
+        };
+
+        // Extract the middleware adjusted context.
+        let Context {
+            dex_program_id,
+            seeds,
+            accounts,
+            pre_instructions,
+            mut post_instructions,
+            post_callbacks,
+            ..
+        } = ctx;
+
+        msg!("calling instructions");
+        // Execute pre instructions.
+        for (ix, acc_infos, seeds) in pre_instructions {
+            invoke_signed(&ix, &acc_infos, &seeds)?;
+        }
+
+        // Execute the main dex relay.
+        let ix = dex_instruction(dex_program_id, &accounts, &ix_data);
+        invoke_signed(&ix, &accounts, &seeds)?;
+
+        // Reverse this to make the Feeze lp Tokens last instruction of program!
+        post_instructions.reverse();
+
+        // Execute post instructions.
+        for (ix, acc_infos, seeds) in post_instructions {
+            invoke_signed(&ix, &acc_infos, &seeds)?;
+        }
+
+        // Execute post callbacks.
+        for (function, accounts, args) in post_callbacks {
+            function(program_id, accounts, ix_data.clone(), args)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the middleware over the request like `run` does, but returns the
+    /// instructions `run` would invoke instead of invoking them. Post
+    /// callbacks are not called.
+    ///
+    /// Returns `None` when the instruction data doesn't match any DEX
+    /// instruction, in which case only the middleware fallbacks run.
+    pub fn dry_run(
+        mut self,
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        data: &[u8],
+    ) -> std::result::Result<Option<DryRun>, ProgramError> {
+        let (ctx, ix_data) = match self.handle(program_id, accounts, data)? {
+            Some(handled) => handled,
+            None => return Ok(None),
+        };
+        let without_accounts = |(ix, _, seeds)| (ix, seeds);
+        Ok(Some(DryRun {
+            instruction: dex_instruction(ctx.dex_program_id, &ctx.accounts, &ix_data),
+            seeds: ctx.seeds,
+            pre_instructions: ctx
+                .pre_instructions
+                .into_iter()
+                .map(without_accounts)
+                .collect(),
+            post_instructions: ctx
+                .post_instructions
+                .into_iter()
+                .rev()
+                .map(without_accounts)
+                .collect(),
+        }))
+    }
+
+    /// Runs the middleware over the request, returning the middleware
+    /// adjusted context and DEX instruction data, or `None` when the request
+    /// was handled by the fallbacks.
+    fn handle<'b, 'info>(
+        &mut self,
+        program_id: &'b Pubkey,
+        accounts: &'b [AccountInfo<'info>],
+        data: &[u8],
+    ) -> std::result::Result<Option<(Context<'b, 'info>, Vec<u8>)>, ProgramError> {
         let mut ix_data = data;
 
         // First account is the Serum DEX executable--used for CPI.
+        require!(!accounts.is_empty(), ErrorCode::NotEnoughAccounts);
         let dex = &accounts[0];
-        // require!(dex.key == &dex::ID, ErrorCode::InvalidTargetProgram);
+        require!(
+            self.is_dex_program(dex.key),
+            ErrorCode::InvalidTargetProgram
+        );
         let acc_infos = (&accounts[1..]).to_vec();
 
         // Process the instruction data.
@@ -116,86 +234,64 @@ impl<'a> MarketProxy<'a> {
                 for mw in &self.middlewares {
                     mw.fallback(&mut ctx)?;
                 }
-                return Ok(());
+                return Ok(None);
             }
         };
 
-        let ix_data_vec = MarketInstruction::pack(&ix.unwrap());
-        ix_data = ix_data_vec.as_slice();
-
-        // Extract the middleware adjusted context.
-        let Context {
-            seeds,
-            accounts,
-            pre_instructions,
-            mut post_instructions,
-            post_callbacks,
-            ..
-        } = ctx;
+        Ok(Some((ctx, MarketInstruction::pack(&ix.unwrap()))))
+    }
+}
 
-        msg!("calling instructions");
-        // Execute pre instructions.
-        for (ix, acc_infos, seeds) in pre_instructions {
-            let tmp_signers: Vec<Vec<&[u8]>> = seeds
-                .iter()
-                .map(|seeds| {
-                    let seeds: Vec<&[u8]> = seeds.iter().map(|seed| &seed[..]).collect();
-                    seeds
-                })
-                .collect();
-            let signers: Vec<&[&[u8]]> = tmp_signers.iter().map(|seeds| &seeds[..]).collect();
-            program::invoke_signed(&ix, &acc_infos, &signers)?;
-        }
+/// Instructions a request would invoke, as returned by
+/// `MarketProxy::dry_run`.
+#[derive(Debug)]
+pub struct DryRun {
+    /// Instruction relayed to the DEX, with the middleware adjusted accounts.
+    pub instruction: Instruction,
+    /// Seeds of the PDAs signing the DEX instruction.
+    pub seeds: Seeds,
+    /// Instructions invoked before the DEX instruction, with their seeds.
+    pub pre_instructions: Vec<(Instruction, Seeds)>,
+    /// Instructions invoked after the DEX instruction, in invocation order.
+    pub post_instructions: Vec<(Instruction, Seeds)>,
+}
 
-        // Execute the main dex relay.
-        {
-            let tmp_signers: Vec<Vec<&[u8]>> = seeds
-                .iter()
-                .map(|seeds| {
-                    let seeds: Vec<&[u8]> = seeds.iter().map(|seed| &seed[..]).collect();
-                    seeds
-                })
-                .collect();
-            let signers: Vec<&[&[u8]]> = tmp_signers.iter().map(|seeds| &seeds[..]).collect();
-
-            // CPI to the DEX.
-            let dex_accounts = accounts
-                .iter()
-                .map(|acc| AccountMeta {
-                    pubkey: *acc.key,
-                    is_signer: acc.is_signer,
-                    is_writable: acc.is_writable,
-                })
-                .collect();
-            let ix = anchor_lang::solana_program::instruction::Instruction {
-                data: ix_data.to_vec(),
-                accounts: dex_accounts,
-                program_id: dex.key(),
-            };
-            program::invoke_signed(&ix, &accounts, &signers)?;
-        }
+fn dex_instruction(dex_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> Instruction {
+    let accounts = accounts
+        .iter()
+        .map(|acc| AccountMeta {
+            pubkey: *acc.key,
+            is_signer: acc.is_signer,
+            is_writable: acc.is_writable,
+        })
+        .collect();
+    Instruction {
+        data: data.to_vec(),
+        accounts,
+        program_id: *dex_program_id,
+    }
+}
 
-        // Reverse this to make the Feeze lp Tokens last instruction of program!
-        post_instructions.reverse();
+fn invoke_signed(ix: &Instruction, accounts: &[AccountInfo], seeds: &Seeds) -> ProgramResult {
+    let tmp_signers: Vec<Vec<&[u8]>> = seeds
+        .iter()
+        .map(|seeds| {
+            let seeds: Vec<&[u8]> = seeds.iter().map(|seed| &seed[..]).collect();
+            seeds
+        })
+        .collect();
+    let signers: Vec<&[&[u8]]> = tmp_signers.iter().map(|seeds| &seeds[..]).collect();
+    program::invoke_signed(ix, accounts, &signers)
+}
 
-        // Execute post instructions.
-        for (ix, acc_infos, seeds) in post_instructions {
-            let tmp_signers: Vec<Vec<&[u8]>> = seeds
-                .iter()
-                .map(|seeds| {
-                    let seeds: Vec<&[u8]> = seeds.iter().map(|seed| &seed[..]).collect();
-                    seeds
-                })
-                .collect();
-            let signers: Vec<&[&[u8]]> = tmp_signers.iter().map(|seeds| &seeds[..]).collect();
-            program::invoke_signed(&ix, &acc_infos, &signers)?;
-        }
+// Constants.
 
-        // Execute post callbacks.
-        for (function, accounts, args) in post_callbacks {
-            function(program_id, accounts, ix_data.to_vec(), args)?;
-        }
+pub mod serum_v3 {
+    use anchor_lang::prelude::declare_id;
+    declare_id!("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin");
+}
 
-        Ok(())
-    }
+pub mod openbook_v1 {
+    use anchor_lang::prelude::declare_id;
+    declare_id!("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX");
 }
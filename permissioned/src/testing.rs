@@ -0,0 +1,554 @@
+//! Fake accounts and requests to unit test market middleware without a
+//! validator, through a `Context` or `MarketProxy::dry_run`.
+
+use crate::{open_orders_index_seed, Context, TradingDelegate};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::SUCCESS;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::solana_program::{bpf_loader, system_program, sysvar};
+use serum_dex::state::{
+    gen_vault_signer_key, AccountFlag, MarketState, OpenOrders, ACCOUNT_HEAD_PADDING,
+    ACCOUNT_TAIL_PADDING,
+};
+use solana_gateway::state::GatewayToken;
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use std::mem::size_of;
+
+/// An account owning its lamports and data, lending them to an
+/// `AccountInfo`.
+#[derive(Clone, Debug)]
+pub struct TestAccount {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub is_signer: bool,
+    pub is_writable: bool,
+    pub executable: bool,
+}
+
+impl TestAccount {
+    /// Rent exempt account with the given data.
+    pub fn new(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> Self {
+        Self {
+            key,
+            owner,
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        }
+    }
+
+    /// Wallet, or any other account of the system program without data.
+    pub fn wallet(key: Pubkey) -> Self {
+        Self::new(key, system_program::ID, Vec::new())
+    }
+
+    pub fn program(key: Pubkey) -> Self {
+        Self {
+            executable: true,
+            ..Self::new(key, bpf_loader::ID, Vec::new())
+        }
+    }
+
+    pub fn rent() -> Self {
+        let data = bincode::serialize(&Rent::default()).unwrap();
+        Self::new(sysvar::rent::ID, sysvar::ID, data)
+    }
+
+    pub fn mint(key: Pubkey, authority: Pubkey, decimals: u8) -> Self {
+        let mint = Mint {
+            mint_authority: Some(authority).into(),
+            decimals,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        let mut data = vec![0; Mint::LEN];
+        mint.pack_into_slice(&mut data);
+        Self::new(key, spl_token::ID, data)
+    }
+
+    pub fn token_account(key: Pubkey, mint: Pubkey, authority: Pubkey, amount: u64) -> Self {
+        let account = TokenAccount {
+            mint,
+            owner: authority,
+            amount,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        let mut data = vec![0; TokenAccount::LEN];
+        account.pack_into_slice(&mut data);
+        Self::new(key, spl_token::ID, data)
+    }
+
+    /// Serum open orders account of the given owner.
+    pub fn open_orders(key: Pubkey, dex_program_id: Pubkey, market: Pubkey, owner: Pubkey) -> Self {
+        let open_orders = OpenOrders {
+            account_flags: (AccountFlag::Initialized as u64) | (AccountFlag::OpenOrders as u64),
+            market: bytemuck::cast(market.to_bytes()),
+            owner: bytemuck::cast(owner.to_bytes()),
+            free_slot_bits: u128::MAX,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        Self::new(
+            key,
+            dex_program_id,
+            with_serum_padding(bytemuck::bytes_of(&open_orders)),
+        )
+    }
+
+    /// Trading delegate record, owned by the proxy program.
+    pub fn trading_delegate(
+        key: Pubkey,
+        program_id: Pubkey,
+        trading_delegate: &TradingDelegate,
+    ) -> Self {
+        let mut data = Vec::new();
+        trading_delegate.try_serialize(&mut data).unwrap();
+        Self::new(key, program_id, data)
+    }
+
+    /// Civic gateway token of the user, expiring at the given time if any.
+    pub fn gateway_token(
+        key: Pubkey,
+        user: Pubkey,
+        gatekeeper_network: Pubkey,
+        expire_time: Option<i64>,
+    ) -> Self {
+        let token = GatewayToken::new_vanilla(
+            &user,
+            &gatekeeper_network,
+            &Pubkey::new_unique(),
+            &expire_time,
+        );
+        Self::new(key, gateway_program::ID, token.try_to_vec().unwrap())
+    }
+
+    pub fn signer(mut self) -> Self {
+        self.is_signer = true;
+        self
+    }
+
+    pub fn writable(mut self) -> Self {
+        self.is_writable = true;
+        self
+    }
+
+    /// Freezes a token account.
+    pub fn frozen(mut self) -> Self {
+        let mut account = TokenAccount::unpack(&self.data).unwrap();
+        account.state = AccountState::Frozen;
+        account.pack_into_slice(&mut self.data);
+        self
+    }
+
+    pub fn account_info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            self.is_signer,
+            self.is_writable,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            self.executable,
+            0,
+        )
+    }
+}
+
+/// Accounts of a request to the proxy.
+pub struct TestRequest {
+    pub dex_program: TestAccount,
+    /// Accounts after the DEX program account.
+    pub accounts: Vec<TestAccount>,
+}
+
+impl TestRequest {
+    pub fn new(dex_program_id: Pubkey, accounts: Vec<TestAccount>) -> Self {
+        Self {
+            dex_program: TestAccount::program(dex_program_id),
+            accounts,
+        }
+    }
+
+    /// Adds accounts in front of the DEX accounts, e.g. accounts that a
+    /// middleware strips.
+    pub fn prepend(mut self, accounts: Vec<TestAccount>) -> Self {
+        self.accounts.splice(0..0, accounts);
+        self
+    }
+
+    /// Adds an account after the DEX accounts.
+    pub fn push(mut self, account: TestAccount) -> Self {
+        self.accounts.push(account);
+        self
+    }
+
+    /// Context of the request, as the proxy hands it to the middleware.
+    pub fn context<'a>(&'a mut self, program_id: &'a Pubkey) -> Context<'a, 'a> {
+        let accounts = self
+            .accounts
+            .iter_mut()
+            .map(TestAccount::account_info)
+            .collect();
+        Context::new(program_id, &self.dex_program.key, accounts)
+    }
+
+    /// Accounts of the request, to pass to `MarketProxy::dry_run`.
+    pub fn account_infos(&mut self) -> Vec<AccountInfo<'_>> {
+        std::iter::once(&mut self.dex_program)
+            .chain(self.accounts.iter_mut())
+            .map(TestAccount::account_info)
+            .collect()
+    }
+}
+
+/// Serum market of fake accounts, building the requests of each DEX
+/// instruction with the account layout `OpenOrdersPda` expects.
+pub struct TestMarket {
+    pub program_id: Pubkey,
+    pub dex_program_id: Pubkey,
+    pub market: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub vault_signer: Pubkey,
+    pub vault_signer_nonce: u64,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+}
+
+impl TestMarket {
+    /// Market of the given DEX, proxied by the given program.
+    pub fn new(program_id: Pubkey, dex_program_id: Pubkey) -> Self {
+        let market = Pubkey::new_unique();
+        let (vault_signer_nonce, vault_signer) = (0..)
+            .find_map(|nonce| {
+                gen_vault_signer_key(nonce, &market, &dex_program_id)
+                    .ok()
+                    .map(|key| (nonce, key))
+            })
+            .unwrap();
+        Self {
+            program_id,
+            dex_program_id,
+            market,
+            request_queue: Pubkey::new_unique(),
+            event_queue: Pubkey::new_unique(),
+            bids: Pubkey::new_unique(),
+            asks: Pubkey::new_unique(),
+            coin_mint: Pubkey::new_unique(),
+            pc_mint: Pubkey::new_unique(),
+            coin_vault: Pubkey::new_unique(),
+            pc_vault: Pubkey::new_unique(),
+            vault_signer,
+            vault_signer_nonce,
+            coin_lot_size: 1_000,
+            pc_lot_size: 10,
+        }
+    }
+
+    /// Market account, a permissioned market.
+    pub fn market_account(&self) -> TestAccount {
+        let state = MarketState {
+            account_flags: (AccountFlag::Initialized as u64)
+                | (AccountFlag::Market as u64)
+                | (AccountFlag::Permissioned as u64),
+            own_address: bytemuck::cast(self.market.to_bytes()),
+            vault_signer_nonce: self.vault_signer_nonce,
+            coin_mint: bytemuck::cast(self.coin_mint.to_bytes()),
+            pc_mint: bytemuck::cast(self.pc_mint.to_bytes()),
+            coin_vault: bytemuck::cast(self.coin_vault.to_bytes()),
+            pc_vault: bytemuck::cast(self.pc_vault.to_bytes()),
+            req_q: bytemuck::cast(self.request_queue.to_bytes()),
+            event_q: bytemuck::cast(self.event_queue.to_bytes()),
+            bids: bytemuck::cast(self.bids.to_bytes()),
+            asks: bytemuck::cast(self.asks.to_bytes()),
+            coin_lot_size: self.coin_lot_size,
+            pc_lot_size: self.pc_lot_size,
+            ..bytemuck::Zeroable::zeroed()
+        };
+        TestAccount::new(
+            self.market,
+            self.dex_program_id,
+            with_serum_padding(bytemuck::bytes_of(&state)),
+        )
+    }
+
+    /// Open orders PDA of the owner.
+    pub fn open_orders(&self, owner: &Pubkey, index: u8) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"open-orders",
+                self.dex_program_id.as_ref(),
+                self.market.as_ref(),
+                owner.as_ref(),
+                open_orders_index_seed(index).as_ref(),
+            ],
+            &self.program_id,
+        )
+    }
+
+    pub fn open_orders_init_authority(&self) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                b"open-orders-init",
+                self.dex_program_id.as_ref(),
+                self.market.as_ref(),
+            ],
+            &self.program_id,
+        )
+    }
+
+    /// Open orders account of the owner, the PDA owning itself.
+    pub fn open_orders_account(&self, owner: &Pubkey, index: u8) -> TestAccount {
+        let (open_orders, _) = self.open_orders(owner, index);
+        TestAccount::open_orders(open_orders, self.dex_program_id, self.market, open_orders)
+    }
+
+    /// Coin token account of the owner.
+    pub fn coin_wallet(&self, owner: &Pubkey, amount: u64) -> TestAccount {
+        TestAccount::token_account(Pubkey::new_unique(), self.coin_mint, *owner, amount).writable()
+    }
+
+    /// Price currency token account of the owner.
+    pub fn pc_wallet(&self, owner: &Pubkey, amount: u64) -> TestAccount {
+        TestAccount::token_account(Pubkey::new_unique(), self.pc_mint, *owner, amount).writable()
+    }
+
+    /// Accounts:
+    ///
+    /// 0. Dex program.
+    /// 1. System program.
+    ///
+    /// .. serum_dex::MarketInstruction::InitOpenOrders.
+    ///
+    /// The open orders init authority is the last DEX account.
+    pub fn init_open_orders(&self, owner: &Pubkey, index: u8) -> TestRequest {
+        let (open_orders, _) = self.open_orders(owner, index);
+        let (init_authority, _) = self.open_orders_init_authority();
+        // CPIs do nothing off-chain, so the open orders account is handed
+        // over as the system program would leave it.
+        let open_orders = TestAccount::new(
+            open_orders,
+            self.dex_program_id,
+            vec![0; size_of::<OpenOrders>() + SERUM_PADDING],
+        );
+        self.request(vec![
+            TestAccount::program(self.dex_program_id),
+            TestAccount::program(system_program::ID),
+            open_orders.writable(),
+            TestAccount::wallet(*owner).signer().writable(),
+            self.market_account(),
+            TestAccount::rent(),
+            TestAccount::wallet(init_authority),
+        ])
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::NewOrderV3.
+    pub fn new_order_v3(&self, owner: &Pubkey, index: u8, order_payer: TestAccount) -> TestRequest {
+        self.request(vec![
+            self.market_account().writable(),
+            self.open_orders_account(owner, index).writable(),
+            TestAccount::wallet(self.request_queue).writable(),
+            TestAccount::wallet(self.event_queue).writable(),
+            TestAccount::wallet(self.bids).writable(),
+            TestAccount::wallet(self.asks).writable(),
+            order_payer.writable(),
+            TestAccount::wallet(*owner).signer(),
+            TestAccount::wallet(self.coin_vault).writable(),
+            TestAccount::wallet(self.pc_vault).writable(),
+            TestAccount::program(spl_token::ID),
+            TestAccount::rent(),
+        ])
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::CancelOrderV2.
+    pub fn cancel_order_v2(&self, owner: &Pubkey, index: u8) -> TestRequest {
+        self.request(vec![
+            self.market_account(),
+            TestAccount::wallet(self.bids).writable(),
+            TestAccount::wallet(self.asks).writable(),
+            self.open_orders_account(owner, index).writable(),
+            TestAccount::wallet(*owner).signer(),
+            TestAccount::wallet(self.event_queue).writable(),
+        ])
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::CancelOrderByClientIdV2.
+    pub fn cancel_order_by_client_id_v2(&self, owner: &Pubkey, index: u8) -> TestRequest {
+        self.cancel_order_v2(owner, index)
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::SettleFunds.
+    pub fn settle_funds(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        coin_wallet: TestAccount,
+        pc_wallet: TestAccount,
+        referral_pc_wallet: Option<TestAccount>,
+    ) -> TestRequest {
+        let mut accounts = vec![
+            self.market_account().writable(),
+            self.open_orders_account(owner, index).writable(),
+            TestAccount::wallet(*owner).signer(),
+            TestAccount::wallet(self.coin_vault).writable(),
+            TestAccount::wallet(self.pc_vault).writable(),
+            coin_wallet.writable(),
+            pc_wallet.writable(),
+            TestAccount::wallet(self.vault_signer),
+            TestAccount::program(spl_token::ID),
+        ];
+        accounts.extend(referral_pc_wallet.map(TestAccount::writable));
+        self.request(accounts)
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::CloseOpenOrders.
+    pub fn close_open_orders(
+        &self,
+        owner: &Pubkey,
+        index: u8,
+        destination: &Pubkey,
+    ) -> TestRequest {
+        self.request(vec![
+            self.open_orders_account(owner, index).writable(),
+            TestAccount::wallet(*owner).signer(),
+            TestAccount::wallet(*destination).writable(),
+            self.market_account(),
+        ])
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::ConsumeEvents.
+    pub fn consume_events(&self, open_orders: &[Pubkey]) -> TestRequest {
+        let mut accounts: Vec<TestAccount> = open_orders
+            .iter()
+            .map(|key| {
+                TestAccount::open_orders(*key, self.dex_program_id, self.market, *key).writable()
+            })
+            .collect();
+        accounts.extend(vec![
+            self.market_account().writable(),
+            TestAccount::wallet(self.event_queue).writable(),
+            self.coin_wallet(&Pubkey::new_unique(), 0),
+            self.pc_wallet(&Pubkey::new_unique(), 0),
+        ]);
+        self.request(accounts)
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::ConsumeEventsPermissioned.
+    pub fn consume_events_permissioned(
+        &self,
+        open_orders: &[Pubkey],
+        consume_events_authority: &Pubkey,
+    ) -> TestRequest {
+        let mut accounts: Vec<TestAccount> = open_orders
+            .iter()
+            .map(|key| {
+                TestAccount::open_orders(*key, self.dex_program_id, self.market, *key).writable()
+            })
+            .collect();
+        accounts.extend(vec![
+            self.market_account().writable(),
+            TestAccount::wallet(self.event_queue).writable(),
+            TestAccount::wallet(*consume_events_authority).signer(),
+        ]);
+        self.request(accounts)
+    }
+
+    /// Accounts:
+    ///
+    /// .. serum_dex::MarketInstruction::Prune.
+    pub fn prune(&self, prune_authority: &Pubkey, owner: &Pubkey, index: u8) -> TestRequest {
+        let open_orders = self.open_orders_account(owner, index).writable();
+        let open_orders_owner = TestAccount::wallet(open_orders.key);
+        self.request(vec![
+            self.market_account().writable(),
+            TestAccount::wallet(self.bids).writable(),
+            TestAccount::wallet(self.asks).writable(),
+            TestAccount::wallet(*prune_authority).signer(),
+            open_orders,
+            open_orders_owner,
+            TestAccount::wallet(self.event_queue).writable(),
+        ])
+    }
+
+    fn request(&self, accounts: Vec<TestAccount>) -> TestRequest {
+        TestRequest::new(self.dex_program_id, accounts)
+    }
+}
+
+/// Serves the clock and rent sysvars off-chain, where `Clock::get` and
+/// `Rent::get` fail otherwise. CPIs made by middleware, like the creation of
+/// the open orders account by `OpenOrdersPda`, do nothing.
+///
+/// The sysvars are shared by the whole process.
+pub fn set_sysvars(clock: Clock, rent: Rent) {
+    set_syscall_stubs(Box::new(SysvarStubs { clock, rent }));
+}
+
+struct SysvarStubs {
+    clock: Clock,
+    rent: Rent,
+}
+
+impl SyscallStubs for SysvarStubs {
+    fn sol_invoke_signed(
+        &self,
+        _instruction: &Instruction,
+        _account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        Ok(())
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Clock) = self.clock.clone() };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = self.rent };
+        SUCCESS
+    }
+}
+
+fn with_serum_padding(data: &[u8]) -> Vec<u8> {
+    let mut padded =
+        Vec::with_capacity(ACCOUNT_HEAD_PADDING.len() + data.len() + ACCOUNT_TAIL_PADDING.len());
+    padded.extend_from_slice(ACCOUNT_HEAD_PADDING);
+    padded.extend_from_slice(data);
+    padded.extend_from_slice(ACCOUNT_TAIL_PADDING);
+    padded
+}
+
+// Civic gateway program, whose id `solana_gateway` keeps private.
+mod gateway_program {
+    anchor_lang::declare_id!("gatem74V238djXdzWnJf94Wo1DcnuGkfijbf3AuBhfs");
+}
+
+// b"serum".len() + b"padding".len().
+const SERUM_PADDING: usize = 12;
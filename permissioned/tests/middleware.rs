@@ -0,0 +1,411 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program_pack::Pack;
+use serum_dex::instruction::{
+    CancelOrderInstructionV2, MarketInstruction, NewOrderInstructionV3, SelfTradeBehavior,
+};
+use serum_dex::matching::{OrderType, Side};
+use serum_dex_permissioned::testing::{set_sysvars, TestAccount, TestMarket};
+use serum_dex_permissioned::{
+    open_orders_authority, serum_dex, serum_v3, ErrorCode, GatewayCheck, MarketMiddleware,
+    MarketProxy, OpenOrdersPda, ReferralFees, TradingDelegate, CANCEL_ORDERS,
+};
+use spl_token::state::{Account as TokenAccount, AccountState};
+use std::num::NonZeroU64;
+
+// Unix timestamp of the clock sysvar, shared by all tests.
+const NOW: i64 = 1_640_000_000;
+
+fn market() -> TestMarket {
+    set_sysvars(
+        Clock {
+            unix_timestamp: NOW,
+            ..Clock::default()
+        },
+        Rent::default(),
+    );
+    TestMarket::new(Pubkey::new_unique(), Pubkey::new_unique())
+}
+
+fn ask(max_coin_lots: u64) -> MarketInstruction {
+    MarketInstruction::NewOrderV3(new_order(max_coin_lots))
+}
+
+fn new_order(max_coin_lots: u64) -> NewOrderInstructionV3 {
+    NewOrderInstructionV3 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(1_000).unwrap(),
+        max_coin_qty: NonZeroU64::new(max_coin_lots).unwrap(),
+        max_native_pc_qty_including_fees: NonZeroU64::new(u64::MAX).unwrap(),
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        order_type: OrderType::Limit,
+        client_order_id: 0,
+        limit: 10,
+    }
+}
+
+// Instruction data of `OpenOrdersPda`, the discriminant and its arguments
+// followed by the DEX instruction.
+fn data(prefix: &[u8], ix: MarketInstruction) -> Vec<u8> {
+    let mut data = prefix.to_vec();
+    data.extend(ix.pack());
+    data
+}
+
+#[test]
+fn init_open_orders_is_signed_by_the_pdas() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let (open_orders, bump) = market.open_orders(&owner, 0);
+    let (init_authority, bump_init) = market.open_orders_init_authority();
+    let mut request = market.init_open_orders(&owner, 0);
+
+    let mut open_orders_pda = OpenOrdersPda::new();
+    let dry_run = MarketProxy::new()
+        .dex_program(market.dex_program_id)
+        .middleware(&mut open_orders_pda)
+        .dry_run(
+            &market.program_id,
+            &request.account_infos(),
+            &data(&[0, bump, bump_init], MarketInstruction::InitOpenOrders),
+        )
+        .unwrap()
+        .unwrap();
+
+    let accounts = &dry_run.instruction.accounts;
+    assert_eq!(dry_run.instruction.program_id, market.dex_program_id);
+    assert_eq!(accounts.len(), 5);
+    assert_eq!(accounts[0].pubkey, open_orders);
+    assert_eq!(accounts[1].pubkey, open_orders);
+    assert!(accounts[1].is_signer);
+    assert_eq!(accounts[2].pubkey, market.market);
+    assert_eq!(accounts[4].pubkey, init_authority);
+    assert!(accounts[4].is_signer);
+    assert_eq!(dry_run.seeds.len(), 2);
+}
+
+#[test]
+fn requests_to_dex_programs_outside_the_allowlist_are_rejected() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let (_, bump) = market.open_orders(&owner, 0);
+    let (_, bump_init) = market.open_orders_init_authority();
+    let mut request = market.init_open_orders(&owner, 0);
+    let data = data(&[0, bump, bump_init], MarketInstruction::InitOpenOrders);
+
+    // The market's DEX program isn't one of the defaults.
+    let mut open_orders_pda = OpenOrdersPda::new();
+    let err = MarketProxy::new()
+        .middleware(&mut open_orders_pda)
+        .dry_run(&market.program_id, &request.account_infos(), &data)
+        .unwrap_err();
+    assert_eq!(err, ErrorCode::InvalidTargetProgram.into());
+
+    let mut open_orders_pda = OpenOrdersPda::new();
+    let err = MarketProxy::new()
+        .dex_programs(vec![serum_v3::ID, Pubkey::new_unique()])
+        .middleware(&mut open_orders_pda)
+        .dry_run(&market.program_id, &request.account_infos(), &data)
+        .unwrap_err();
+    assert_eq!(err, ErrorCode::InvalidTargetProgram.into());
+
+    let mut open_orders_pda = OpenOrdersPda::new();
+    let dry_run = MarketProxy::new()
+        .dex_programs(vec![market.dex_program_id])
+        .middleware(&mut open_orders_pda)
+        .dry_run(&market.program_id, &request.account_infos(), &data)
+        .unwrap()
+        .unwrap();
+    assert_eq!(dry_run.instruction.program_id, market.dex_program_id);
+}
+
+#[test]
+fn new_order_is_signed_by_the_open_orders_pda() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let (open_orders, bump) = market.open_orders(&owner, 0);
+    let payer = market.coin_wallet(&owner, 10 * market.coin_lot_size);
+    let payer_key = payer.key;
+    let mut request = market.new_order_v3(&owner, 0, payer);
+
+    let mut open_orders_pda = OpenOrdersPda::new();
+    let dry_run = MarketProxy::new()
+        .dex_program(market.dex_program_id)
+        .middleware(&mut open_orders_pda)
+        .dry_run(
+            &market.program_id,
+            &request.account_infos(),
+            &data(&[1], ask(5)),
+        )
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(dry_run.instruction.accounts[7].pubkey, open_orders);
+    assert!(dry_run.instruction.accounts[7].is_signer);
+    assert_eq!(
+        dry_run.seeds,
+        vec![open_orders_authority! {
+            program = &market.program_id,
+            dex_program = market.dex_program_id,
+            market = market.market,
+            authority = owner,
+            bump = bump
+        }]
+    );
+
+    // The open orders account may spend the coins of the order, and no more.
+    let approve = spl_token::instruction::approve(
+        &spl_token::ID,
+        &payer_key,
+        &open_orders,
+        &owner,
+        &[],
+        5 * market.coin_lot_size,
+    )
+    .unwrap();
+    let revoke = spl_token::instruction::revoke(&spl_token::ID, &payer_key, &owner, &[]).unwrap();
+    assert_eq!(dry_run.pre_instructions, vec![(approve, Vec::new())]);
+    assert_eq!(dry_run.post_instructions, vec![(revoke, Vec::new())]);
+}
+
+#[test]
+fn new_order_of_the_owner_restores_the_standing_approval() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let (open_orders, _) = market.open_orders(&owner, 0);
+    // Approval trading delegates pay their orders with.
+    let payer = TokenAccount {
+        mint: market.coin_mint,
+        owner,
+        amount: 10 * market.coin_lot_size,
+        delegate: Some(open_orders).into(),
+        delegated_amount: 7 * market.coin_lot_size,
+        state: AccountState::Initialized,
+        ..TokenAccount::default()
+    };
+    let mut payer_data = vec![0; TokenAccount::LEN];
+    payer.pack_into_slice(&mut payer_data);
+    let payer = TestAccount::new(Pubkey::new_unique(), spl_token::ID, payer_data).writable();
+    let payer_key = payer.key;
+    let mut request = market.new_order_v3(&owner, 0, payer);
+
+    let mut open_orders_pda = OpenOrdersPda::new();
+    let dry_run = MarketProxy::new()
+        .dex_program(market.dex_program_id)
+        .middleware(&mut open_orders_pda)
+        .dry_run(
+            &market.program_id,
+            &request.account_infos(),
+            &data(&[1], ask(5)),
+        )
+        .unwrap()
+        .unwrap();
+
+    let restore = spl_token::instruction::approve(
+        &spl_token::ID,
+        &payer_key,
+        &open_orders,
+        &owner,
+        &[],
+        7 * market.coin_lot_size,
+    )
+    .unwrap();
+    assert_eq!(dry_run.post_instructions, vec![(restore, Vec::new())]);
+}
+
+#[test]
+fn new_order_requires_the_owner_signature() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let payer = market.coin_wallet(&owner, 10 * market.coin_lot_size);
+    let mut request = market.new_order_v3(&owner, 0, payer);
+    request.accounts[7].is_signer = false;
+
+    let mut open_orders_pda = OpenOrdersPda::new();
+    let err = MarketProxy::new()
+        .dex_program(market.dex_program_id)
+        .middleware(&mut open_orders_pda)
+        .dry_run(
+            &market.program_id,
+            &request.account_infos(),
+            &data(&[1], ask(5)),
+        )
+        .unwrap_err();
+
+    assert_eq!(err, ErrorCode::UnauthorizedUser.into());
+}
+
+#[test]
+fn delegates_cancel_until_expiry() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let delegate = Pubkey::new_unique();
+    let (open_orders, _) = market.open_orders(&owner, 0);
+    let cancel = || {
+        MarketInstruction::CancelOrderV2(CancelOrderInstructionV2 {
+            side: Side::Ask,
+            order_id: 1,
+        })
+    };
+
+    for (expiry, authorized) in [(NOW + 1, true), (NOW, false)] {
+        let trading_delegate = TradingDelegate {
+            owner,
+            delegate,
+            permissions: CANCEL_ORDERS,
+            expiry,
+            bump: 0,
+        };
+        let mut request = market
+            .cancel_order_v2(&owner, 0)
+            .push(TestAccount::trading_delegate(
+                Pubkey::new_unique(),
+                market.program_id,
+                &trading_delegate,
+            ))
+            .push(TestAccount::wallet(delegate).signer());
+        request.accounts[4].is_signer = false;
+
+        let mut open_orders_pda = OpenOrdersPda::new();
+        let result = MarketProxy::new()
+            .dex_program(market.dex_program_id)
+            .middleware(&mut open_orders_pda)
+            .dry_run(
+                &market.program_id,
+                &request.account_infos(),
+                &data(&[4, 0], cancel()),
+            );
+
+        match result {
+            Ok(dry_run) if authorized => {
+                let accounts = dry_run.unwrap().instruction.accounts;
+                assert_eq!(accounts.len(), 6);
+                assert_eq!(accounts[4].pubkey, open_orders);
+                assert!(accounts[4].is_signer);
+            }
+            Err(err) if !authorized => {
+                assert_eq!(err, ErrorCode::UnauthorizedDelegate.into())
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn referral_fees_go_to_the_referral() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let referral = Pubkey::new_unique();
+
+    for (authority, valid) in [(referral, true), (Pubkey::new_unique(), false)] {
+        let mut request = market.settle_funds(
+            &owner,
+            0,
+            market.coin_wallet(&owner, 0),
+            market.pc_wallet(&owner, 0),
+            Some(market.pc_wallet(&authority, 0)),
+        );
+        let result =
+            ReferralFees::new(referral).settle_funds(&mut request.context(&market.program_id));
+
+        if valid {
+            result.unwrap();
+        } else {
+            assert_eq!(result.unwrap_err(), ErrorCode::InvalidReferral.into());
+        }
+    }
+}
+
+#[test]
+fn gateway_tokens_are_verified() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let gatekeeper_network = Pubkey::new_unique();
+
+    for (network, expire_time, valid) in [
+        (gatekeeper_network, None, true),
+        (gatekeeper_network, Some(NOW + 1), true),
+        (Pubkey::new_unique(), None, false),
+        (gatekeeper_network, Some(NOW - 1), false),
+    ] {
+        let gateway_token =
+            TestAccount::gateway_token(Pubkey::new_unique(), owner, network, expire_time);
+        let payer = market.coin_wallet(&owner, 10 * market.coin_lot_size);
+        let mut request = market
+            .new_order_v3(&owner, 0, payer)
+            .prepend(vec![gateway_token]);
+        let result = GatewayCheck::new(gatekeeper_network)
+            .new_order_v3(&mut request.context(&market.program_id), &mut new_order(5));
+
+        if valid {
+            result.unwrap();
+        } else {
+            assert_eq!(result.unwrap_err(), ErrorCode::InvalidGatewayToken.into());
+        }
+    }
+}
+
+#[test]
+fn gateway_check_rejects_short_account_lists() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let gatekeeper_network = Pubkey::new_unique();
+    let gateway_token =
+        TestAccount::gateway_token(Pubkey::new_unique(), owner, gatekeeper_network, None);
+    let mut request = market
+        .init_open_orders(&owner, 0)
+        .prepend(vec![gateway_token]);
+    request.accounts.truncate(4);
+
+    let err = GatewayCheck::new(gatekeeper_network)
+        .init_open_orders(&mut request.context(&market.program_id))
+        .unwrap_err();
+
+    assert_eq!(err, ErrorCode::NotEnoughAccounts.into());
+}
+
+#[test]
+fn gateway_check_leaves_delegated_orders_to_open_orders_pda() {
+    let market = market();
+    let owner = Pubkey::new_unique();
+    let gatekeeper_network = Pubkey::new_unique();
+
+    for (discriminant, result) in [(4, Ok(())), (1, Err(ErrorCode::UnauthorizedUser.into()))] {
+        let gateway_token =
+            TestAccount::gateway_token(Pubkey::new_unique(), owner, gatekeeper_network, None);
+        let payer = market.coin_wallet(&owner, 10 * market.coin_lot_size);
+        let mut request = market
+            .new_order_v3(&owner, 0, payer)
+            .prepend(vec![gateway_token]);
+        request.accounts[8].is_signer = false;
+
+        let mut gateway_check = GatewayCheck::new(gatekeeper_network);
+        gateway_check
+            .instruction(&mut &data(&[discriminant, 0], ask(5))[..])
+            .unwrap();
+        assert_eq!(
+            gateway_check.new_order_v3(&mut request.context(&market.program_id), &mut new_order(5)),
+            result
+        );
+    }
+
+    // The gateway token is still the one of the owner.
+    let gateway_token = TestAccount::gateway_token(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        gatekeeper_network,
+        None,
+    );
+    let payer = market.coin_wallet(&owner, 10 * market.coin_lot_size);
+    let mut request = market
+        .new_order_v3(&owner, 0, payer)
+        .prepend(vec![gateway_token]);
+    request.accounts[8].is_signer = false;
+    let mut gateway_check = GatewayCheck::new(gatekeeper_network);
+    gateway_check
+        .instruction(&mut &data(&[4, 0], ask(5))[..])
+        .unwrap();
+    let err = gateway_check
+        .new_order_v3(&mut request.context(&market.program_id), &mut new_order(5))
+        .unwrap_err();
+    assert_eq!(err, ErrorCode::InvalidGatewayToken.into());
+}
@@ -0,0 +1,216 @@
+use crate::ErrorCode;
+use anchor_lang::prelude::*;
+use credix::state::GlobalMarketState;
+use serum_dex_permissioned::serum_dex::instruction::NewOrderInstructionV3;
+use serum_dex_permissioned::{Context, MarketMiddleware, MarketView};
+use std::mem::size_of;
+
+pub const ALLOWLIST_SEED: &str = "allowlist";
+
+/// Number of the accounts of `Allowlist`, in front of the DEX accounts.
+pub const ALLOWLIST_ACCOUNTS: usize = 2;
+
+/// Marks a market as allowlisted. Only users with an `AllowlistEntry` for the
+/// market can open orders accounts and place orders on it.
+///
+/// The allowlist can only be closed once its entries are removed, which would
+/// otherwise apply again to a new allowlist of the market.
+#[account]
+#[derive(Default)]
+pub struct MarketAllowlist {
+    pub market: Pubkey,
+    pub authority: Pubkey,
+    pub bump: u8,
+    pub entries: u64,
+}
+
+#[account]
+#[derive(Default)]
+pub struct AllowlistEntry {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct CreateAllowlist<'info> {
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+    #[account(
+        constraint = global_market_state.owner == authority.key() @ ErrorCode::UnauthorizedSigner,
+    )]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+    pub market: AccountInfo<'info>,
+    #[account(
+        init,
+        seeds = [ALLOWLIST_SEED.as_bytes(), market.key.as_ref()],
+        bump = bump,
+        payer = authority,
+        space = size_of::<MarketAllowlist>() + 8,
+    )]
+    pub allowlist: Account<'info, MarketAllowlist>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CloseAllowlist<'info> {
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = authority @ ErrorCode::UnauthorizedSigner,
+        constraint = allowlist.entries == 0 @ ErrorCode::AllowlistNotEmpty,
+        close = authority,
+    )]
+    pub allowlist: Account<'info, MarketAllowlist>,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct AddToAllowlist<'info> {
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+    #[account(mut, has_one = authority @ ErrorCode::UnauthorizedSigner)]
+    pub allowlist: Account<'info, MarketAllowlist>,
+    pub user: AccountInfo<'info>,
+    #[account(
+        init,
+        seeds = [ALLOWLIST_SEED.as_bytes(), allowlist.market.as_ref(), user.key.as_ref()],
+        bump = bump,
+        payer = authority,
+        space = size_of::<AllowlistEntry>() + 8,
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(mut, signer)]
+    pub authority: AccountInfo<'info>,
+    #[account(mut, has_one = authority @ ErrorCode::UnauthorizedSigner)]
+    pub allowlist: Account<'info, MarketAllowlist>,
+    #[account(
+        mut,
+        constraint = allowlist_entry.market == allowlist.market @ ErrorCode::InvalidAllowlist,
+        close = authority,
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+}
+
+pub fn process_create_allowlist(
+    ctx: anchor_lang::prelude::Context<CreateAllowlist>,
+    bump: u8,
+) -> ProgramResult {
+    let market = &ctx.accounts.market;
+    require!(
+        crate::market_proxy().is_dex_program(market.owner),
+        serum_dex_permissioned::ErrorCode::InvalidTargetProgram
+    );
+    let market = MarketView::load(market, market.owner)?;
+    require!(
+        market.coin_mint() == ctx.accounts.global_market_state.lp_token_mint_account,
+        ErrorCode::MarketMintMismatch
+    );
+
+    ctx.accounts.allowlist.market = ctx.accounts.market.key();
+    ctx.accounts.allowlist.authority = ctx.accounts.authority.key();
+    ctx.accounts.allowlist.bump = bump;
+
+    Ok(())
+}
+
+pub fn process_add_to_allowlist(
+    ctx: anchor_lang::prelude::Context<AddToAllowlist>,
+    bump: u8,
+) -> ProgramResult {
+    ctx.accounts.allowlist_entry.market = ctx.accounts.allowlist.market;
+    ctx.accounts.allowlist_entry.user = ctx.accounts.user.key();
+    ctx.accounts.allowlist_entry.bump = bump;
+    let allowlist = &mut ctx.accounts.allowlist;
+    allowlist.entries = allowlist
+        .entries
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+pub fn process_remove_from_allowlist(
+    ctx: anchor_lang::prelude::Context<RemoveFromAllowlist>,
+) -> ProgramResult {
+    let allowlist = &mut ctx.accounts.allowlist;
+    allowlist.entries = allowlist
+        .entries
+        .checked_sub(1)
+        .ok_or(ErrorCode::Underflow)?;
+
+    Ok(())
+}
+
+/// Accounts
+/// 0. `[]` allowlist of the market, uninitialized when the market isn't allowlisted
+/// 1. `[]` allowlist entry of the user
+///
+/// Only `init_open_orders` and `new_order_v3` carry these accounts, in front
+/// of the dex accounts.
+pub struct Allowlist;
+
+impl Allowlist {
+    fn check(ctx: &mut Context, market_idx: usize, user_idx: usize) -> ProgramResult {
+        let allowlist = &ctx.accounts[0];
+        let allowlist_entry = &ctx.accounts[1];
+        let market = ctx.accounts[ALLOWLIST_ACCOUNTS + market_idx].key;
+        let user = ctx.accounts[ALLOWLIST_ACCOUNTS + user_idx].key;
+
+        let (allowlist_address, _) = Pubkey::find_program_address(
+            &[ALLOWLIST_SEED.as_bytes(), market.as_ref()],
+            ctx.program_id,
+        );
+        require!(
+            allowlist.key == &allowlist_address,
+            ErrorCode::InvalidAllowlist
+        );
+
+        // Markets without an allowlist are open to every Credix pass holder.
+        if allowlist.owner == ctx.program_id && !allowlist.data_is_empty() {
+            require!(
+                allowlist_entry.owner == ctx.program_id && !allowlist_entry.data_is_empty(),
+                ErrorCode::NotAllowlisted
+            );
+            let allowlist_entry: Account<AllowlistEntry> = Account::try_from(allowlist_entry)?;
+            require!(
+                &allowlist_entry.market == market && &allowlist_entry.user == user,
+                ErrorCode::NotAllowlisted
+            );
+        }
+
+        ctx.accounts = ctx.accounts[ALLOWLIST_ACCOUNTS..].to_vec();
+        Ok(())
+    }
+}
+
+impl MarketMiddleware for Allowlist {
+    /// Accounts:
+    ///
+    /// 0. Allowlist.
+    /// 1. Allowlist entry.
+    ///
+    /// .. `OpenOrdersPda` accounts of serum_dex::MarketInstruction::InitOpenOrders.
+    fn init_open_orders(&self, ctx: &mut Context) -> ProgramResult {
+        Self::check(ctx, 4, 3)
+    }
+
+    /// Accounts:
+    ///
+    /// 0. Allowlist.
+    /// 1. Allowlist entry.
+    ///
+    /// .. serum_dex::MarketInstruction::NewOrderV3.
+    fn new_order_v3(&self, ctx: &mut Context, _ix: &mut NewOrderInstructionV3) -> ProgramResult {
+        Self::check(ctx, 0, 7)
+    }
+}
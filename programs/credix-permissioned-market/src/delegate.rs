@@ -0,0 +1,57 @@
+use crate::ErrorCode;
+use anchor_lang::prelude::*;
+use serum_dex_permissioned::{TradingDelegate, TRADING_DELEGATE_SEED};
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct SetTradingDelegate<'info> {
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        seeds = [TRADING_DELEGATE_SEED, owner.key.as_ref()],
+        bump = bump,
+        payer = owner,
+        space = TradingDelegate::LEN,
+        owner = crate::ID,
+    )]
+    pub trading_delegate: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeTradingDelegate<'info> {
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(mut)]
+    pub trading_delegate: AccountInfo<'info>,
+}
+
+pub fn process_set_trading_delegate(
+    ctx: anchor_lang::prelude::Context<SetTradingDelegate>,
+    bump: u8,
+    delegate: Pubkey,
+    permissions: u8,
+    expiry: i64,
+) -> ProgramResult {
+    let trading_delegate = TradingDelegate {
+        owner: ctx.accounts.owner.key(),
+        delegate,
+        permissions,
+        expiry,
+        bump,
+    };
+    trading_delegate.store(&ctx.accounts.trading_delegate)
+}
+
+pub fn process_revoke_trading_delegate(
+    ctx: anchor_lang::prelude::Context<RevokeTradingDelegate>,
+) -> ProgramResult {
+    let trading_delegate = TradingDelegate::load(&ctx.accounts.trading_delegate, ctx.program_id)?;
+    require!(
+        trading_delegate.owner == ctx.accounts.owner.key(),
+        ErrorCode::UnauthorizedSigner
+    );
+    TradingDelegate::close(&ctx.accounts.trading_delegate, &ctx.accounts.owner)
+}
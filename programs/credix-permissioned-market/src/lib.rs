@@ -1,12 +1,25 @@
+use allowlist::*;
+pub use allowlist::{AllowlistEntry, MarketAllowlist, ALLOWLIST_ACCOUNTS, ALLOWLIST_SEED};
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::account_info::AccountInfo;
 use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::Instruction;
 use anchor_lang::Accounts;
-use credix::cpi::accounts::FreezeThawLpTokens; //codegen -- anchor rust
+use anchor_spl::token::Mint;
+use credix::cpi::accounts::{FreezeThawDelegatedLpTokens, FreezeThawLpTokens}; //codegen -- anchor rust
+use credix::state::GlobalMarketState;
+use delegate::*;
 use serum_dex_permissioned::serum_dex::instruction::{
     CancelOrderInstructionV2, NewOrderInstructionV3,
 };
-use serum_dex_permissioned::{Context, MarketMiddleware, MarketProxy, OpenOrdersPda, ReferralFees};
+use serum_dex_permissioned::serum_dex::matching::Side;
+use serum_dex_permissioned::{
+    Context, DryRun, MarketMiddleware, MarketProxy, MarketView, OpenOrdersPda, PriceBand,
+    ReferencePrice, ReferralFees, Seeds, CANCEL_ORDERS, PLACE_ORDERS,
+};
+
+mod allowlist;
+mod delegate;
 
 declare_id!("GuPsqCV7H2bw35UFBHvmzNgK28qVe92U5vq7JJigjPUv");
 
@@ -14,23 +27,94 @@ declare_id!("GuPsqCV7H2bw35UFBHvmzNgK28qVe92U5vq7JJigjPUv");
 pub mod credix_permissioned_market {
 
     use super::*;
+    use anchor_lang::prelude::Context;
+
+    pub fn create_allowlist(ctx: Context<CreateAllowlist>, bump: u8) -> ProgramResult {
+        msg!("Create allowlist for market: {}", ctx.accounts.market.key);
+        allowlist::process_create_allowlist(ctx, bump)
+    }
+
+    pub fn close_allowlist(_ctx: Context<CloseAllowlist>) -> ProgramResult {
+        Ok(())
+    }
+
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, bump: u8) -> ProgramResult {
+        msg!("Add user to allowlist: {}", ctx.accounts.user.key);
+        allowlist::process_add_to_allowlist(ctx, bump)
+    }
+
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>) -> ProgramResult {
+        msg!(
+            "Remove user from allowlist: {}",
+            ctx.accounts.allowlist_entry.user
+        );
+        allowlist::process_remove_from_allowlist(ctx)
+    }
+
+    pub fn set_trading_delegate(
+        ctx: Context<SetTradingDelegate>,
+        bump: u8,
+        delegate: Pubkey,
+        permissions: u8,
+        expiry: i64,
+    ) -> ProgramResult {
+        msg!("Set trading delegate: {}", delegate);
+        delegate::process_set_trading_delegate(ctx, bump, delegate, permissions, expiry)
+    }
+
+    pub fn revoke_trading_delegate(ctx: Context<RevokeTradingDelegate>) -> ProgramResult {
+        delegate::process_revoke_trading_delegate(ctx)
+    }
+
     pub fn entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
         if data[0] == 255 && data.len() == 2 {
             let mut acc = accounts;
             CreatePdaAccount::try_accounts(program_id, &mut acc, &[data[1]])?;
             Ok(())
         } else {
-            MarketProxy::new()
-                .middleware(&mut CredixPermissionedMarket::default())
-                .middleware(&mut OpenOrdersPda::new())
-                .middleware(&mut ReferralFees::new(referral::ID))
-                .run(program_id, accounts, data)
+            with_market_proxy(|proxy| proxy.run(program_id, accounts, data))
         }
     }
 }
 
+/// Runs the middleware of the proxy over a request without invoking any
+/// program, returning the instructions the proxy would invoke.
+pub fn dry_run(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> std::result::Result<Option<DryRun>, ProgramError> {
+    with_market_proxy(|proxy| proxy.dry_run(program_id, accounts, data))
+}
+
+/// Proxy relaying requests to the DEX programs markets are allowed on.
+fn market_proxy<'a>() -> MarketProxy<'a> {
+    MarketProxy::new().dex_program(dex::ID)
+}
+
+fn with_market_proxy<T>(f: impl FnOnce(MarketProxy) -> T) -> T {
+    f(market_proxy()
+        .middleware(&mut CredixPermissionedMarket::default())
+        .middleware(&mut Allowlist)
+        .middleware(&mut OpenOrdersPda::new())
+        .middleware(&mut ReferralFees::new(referral::ID)))
+}
+
+/// Instruction of the credix program invoked around the DEX instruction,
+/// with its accounts and the seeds of the proxy signing it.
+type CredixCpi<'info> = (Instruction, Vec<AccountInfo<'info>>, Seeds);
+
+/// Permission of the instructions only the owner signs, refused by credix
+/// for trading delegates.
+const OWNER_ONLY: u8 = 0;
+
+/// Number of the credix accounts of `CredixPermissionedMarket`, in front of
+/// the accounts of every proxied instruction.
+pub const CREDIX_ACCOUNTS: usize = 13;
+
 /// Accounts
-/// 0. `[signer]` LP token holder(buyer)
+/// 0. `[signer]` LP token holder(buyer), owner of the open orders account.
+///    Doesn't sign when a trading delegate trades for it
 /// 1. `[writable]` LP token account
 /// 2. `[signer]` credix_permissioned_pda
 /// 3. `[]` signing_authority
@@ -44,13 +128,80 @@ pub mod credix_permissioned_market {
 /// 11. `[]` rent sysvar
 /// 12. `[]` Gateway token
 /// ... Dex accounts
+///
+/// Requests of a trading delegate end with the trading delegate record and
+/// the delegate, which credix checks again before thawing the LP tokens,
+/// with the permission of the proxied instruction.
 #[derive(Default)]
 struct CredixPermissionedMarket {
     signing_authority_bump: u8,
+    delegated: bool,
 }
 
 impl CredixPermissionedMarket {
-    fn freeze_lp_token_cpi(&self, ctx: &mut Context) {
+    fn freeze_lp_token_cpi(&self, ctx: &mut Context, permission: u8) -> ProgramResult {
+        let cpi = if self.delegated {
+            self.delegated_cpi(
+                ctx,
+                credix::instruction::FreezeDelegatedLpTokens { permission },
+            )?
+        } else {
+            self.cpi(ctx, credix::instruction::FreezeLpTokens)
+        };
+        let seeds = vec![
+            b"signing-authority".to_vec(),
+            vec![self.signing_authority_bump],
+        ];
+        ctx.seeds.push(seeds);
+        ctx.post_instructions.push(cpi);
+        Ok(())
+    }
+
+    fn thaw_lp_token_cpi(&self, ctx: &mut Context, permission: u8) -> ProgramResult {
+        if self.delegated {
+            self.thaw_cpi(
+                ctx,
+                credix::instruction::ThawDelegatedLpTokens { permission },
+            )
+        } else {
+            self.thaw_cpi(ctx, credix::instruction::ThawLpTokens)
+        }
+    }
+
+    /// Thaws LP tokens about to be sold, refused by credix during the lockup
+    /// following a deposit.
+    fn thaw_unlocked_lp_token_cpi(&self, ctx: &mut Context, permission: u8) -> ProgramResult {
+        if self.delegated {
+            self.thaw_cpi(
+                ctx,
+                credix::instruction::ThawUnlockedDelegatedLpTokens { permission },
+            )
+        } else {
+            self.thaw_cpi(ctx, credix::instruction::ThawUnlockedLpTokens)
+        }
+    }
+
+    fn thaw_cpi(&self, ctx: &mut Context, ix: impl anchor_lang::InstructionData) -> ProgramResult {
+        let cpi = if self.delegated {
+            self.delegated_cpi(ctx, ix)?
+        } else {
+            self.cpi(ctx, ix)
+        };
+        let seeds = vec![
+            b"signing-authority".to_vec(),
+            vec![self.signing_authority_bump],
+        ];
+        ctx.seeds.push(seeds);
+        ctx.pre_instructions.push(cpi);
+        Ok(())
+    }
+
+    /// Freeze or thaw instruction of the LP holder signing the request.
+    fn cpi<'info>(
+        &self,
+        ctx: &Context<'_, 'info>,
+        ix: impl anchor_lang::InstructionData,
+    ) -> CredixCpi<'info> {
         let credix_program = ctx.accounts[8].to_account_info();
         let cpi_accounts = FreezeThawLpTokens {
             lp_holder: ctx.accounts[0].to_account_info(),
@@ -68,71 +219,113 @@ impl CredixPermissionedMarket {
             rent: ctx.accounts[11].to_account_info(),
             gateway_token: ctx.accounts[12].to_account_info(),
         };
-        let account_meta = cpi_accounts.to_account_metas(None);
-        let ix = credix::instruction::FreezeLpTokens;
-        let data = anchor_lang::InstructionData::data(&ix);
-        let instruction = anchor_lang::solana_program::instruction::Instruction {
+        let instruction = Instruction {
             program_id: credix_program.key(),
-            accounts: account_meta,
-            data,
+            accounts: cpi_accounts.to_account_metas(None),
+            data: ix.data(),
         };
-        let seeds = vec![
-            b"signing-authority".to_vec(),
-            vec![self.signing_authority_bump],
-        ];
-        ctx.seeds.push(seeds);
-        ctx.post_instructions.push((
+        (
             instruction,
             cpi_accounts.to_account_infos(),
             vec![vec![
                 b"signing-authority".to_vec(),
                 vec![self.signing_authority_bump],
             ]],
-        ));
+        )
     }
 
-    fn thaw_lp_token_cpi(&self, ctx: &mut Context) {
+    /// Freeze or thaw instruction of a trading delegate trading for the LP
+    /// holder. The trading delegate record and the delegate are the last two
+    /// accounts of the request, see `OpenOrdersPda`.
+    fn delegated_cpi<'info>(
+        &self,
+        ctx: &Context<'_, 'info>,
+        ix: impl anchor_lang::InstructionData,
+    ) -> std::result::Result<CredixCpi<'info>, ProgramError> {
+        let len = ctx.accounts.len();
+        require!(
+            len >= CREDIX_ACCOUNTS + 2,
+            ErrorCode::MissingRequiredCpiAccounts
+        );
         let credix_program = ctx.accounts[8].to_account_info();
-        let cpi_accounts = FreezeThawLpTokens {
-            lp_holder: ctx.accounts[0].to_account_info(),
-            lp_token_account: ctx.accounts[1].to_account_info(),
+        let cpi_accounts = FreezeThawDelegatedLpTokens {
             credix_permissioned_pda: CredixPermissionedMarket::prepare_pda(
                 &ctx.accounts[2].to_account_info(),
             ),
+            lp_holder: ctx.accounts[0].to_account_info(),
+            trading_delegate: ctx.accounts[len - 2].to_account_info(),
+            delegate: ctx.accounts[len - 1].to_account_info(),
+            credix_pass: ctx.accounts[6].to_account_info(),
+            lp_token_account: ctx.accounts[1].to_account_info(),
+            global_market_state: ctx.accounts[5].to_account_info(),
             signing_authority: ctx.accounts[3].to_account_info(),
             lp_token_mint_account: ctx.accounts[4].to_account_info(),
-            global_market_state: ctx.accounts[5].to_account_info(),
-            credix_pass: ctx.accounts[6].to_account_info(),
             token_program: ctx.accounts[7].to_account_info(),
-            associated_token_program: ctx.accounts[9].to_account_info(),
-            system_program: ctx.accounts[10].to_account_info(),
-            rent: ctx.accounts[11].to_account_info(),
             gateway_token: ctx.accounts[12].to_account_info(),
         };
-        let account_meta = cpi_accounts.to_account_metas(None);
-
-        let ix = credix::instruction::ThawLpTokens;
-        let data = anchor_lang::InstructionData::data(&ix);
-
-        let instruction = anchor_lang::solana_program::instruction::Instruction {
+        let instruction = Instruction {
             program_id: credix_program.key(),
-            accounts: account_meta,
-            data,
+            accounts: cpi_accounts.to_account_metas(None),
+            data: ix.data(),
         };
-        let seeds = vec![
-            b"signing-authority".to_vec(),
-            vec![self.signing_authority_bump],
-        ];
-        ctx.seeds.push(seeds);
-
-        ctx.pre_instructions.push((
+        Ok((
             instruction,
             cpi_accounts.to_account_infos(),
             vec![vec![
                 b"signing-authority".to_vec(),
                 vec![self.signing_authority_bump],
             ]],
-        ));
+        ))
+    }
+
+    /// Checks that the LP holder is the owner of the open orders account the
+    /// request trades with, found at `owner_idx` of the proxied accounts.
+    fn check_lp_holder(ctx: &Context, owner_idx: usize) -> ProgramResult {
+        let owner = ctx
+            .accounts
+            .get(CREDIX_ACCOUNTS + owner_idx)
+            .ok_or(ErrorCode::MissingRequiredCpiAccounts)?;
+        require!(ctx.accounts[0].key == owner.key, ErrorCode::InvalidLpHolder);
+        Ok(())
+    }
+
+    /// Builds the price band of the market from the NAV of its LP token and
+    /// the band configured on the global market state.
+    fn price_band(&self, ctx: &Context) -> std::result::Result<PriceBand, ProgramError> {
+        let global_market_state: Account<GlobalMarketState> = Account::try_from(&ctx.accounts[5])?;
+        let lp_token_mint_account: Account<Mint> = Account::try_from(&ctx.accounts[4])?;
+        require!(
+            lp_token_mint_account.key() == global_market_state.lp_token_mint_account,
+            ErrorCode::MissingRequiredCpiAccounts
+        );
+        // The band must be the one of the pool whose LP token is traded. The
+        // allowlist accounts precede the market, see `Allowlist`.
+        let market = MarketView::load(
+            &ctx.accounts[CREDIX_ACCOUNTS + ALLOWLIST_ACCOUNTS],
+            ctx.dex_program_id,
+        )?;
+        require!(
+            market.coin_mint() == global_market_state.lp_token_mint_account,
+            ErrorCode::MarketMintMismatch
+        );
+
+        // A band of zero disables the price check, only the halt flag applies.
+        let reference_price = match (
+            global_market_state.price_band_bps,
+            lp_token_mint_account.supply,
+        ) {
+            (0, _) | (_, 0) => None,
+            (_, supply) => Some(ReferencePrice {
+                native_pc: global_market_state.pool_value,
+                native_coin: supply,
+            }),
+        };
+        Ok(PriceBand::new(
+            &market,
+            reference_price,
+            global_market_state.price_band_bps,
+            global_market_state.trading_halted,
+        ))
     }
 
     fn prepare_pda<'info>(acc_info: &AccountInfo<'info>) -> AccountInfo<'info> {
@@ -146,6 +339,8 @@ impl MarketMiddleware for CredixPermissionedMarket {
     fn instruction(&mut self, _data: &mut &[u8]) -> ProgramResult {
         self.signing_authority_bump = _data[0];
         *_data = &_data[1..];
+        // Discriminator of `OpenOrdersPda` for requests of a trading delegate.
+        self.delegated = _data.first() == Some(&4);
         msg!(
             "signing bump credixPermissionedMarket {}",
             self.signing_authority_bump
@@ -154,17 +349,23 @@ impl MarketMiddleware for CredixPermissionedMarket {
     }
 
     fn init_open_orders(&self, ctx: &mut Context) -> ProgramResult {
-        self.thaw_lp_token_cpi(ctx);
-        self.freeze_lp_token_cpi(ctx);
-        ctx.accounts = ctx.accounts[13..].to_vec();
+        Self::check_lp_holder(ctx, ALLOWLIST_ACCOUNTS + 3)?;
+        self.thaw_lp_token_cpi(ctx, OWNER_ONLY)?;
+        self.freeze_lp_token_cpi(ctx, OWNER_ONLY)?;
+        ctx.accounts = ctx.accounts[CREDIX_ACCOUNTS..].to_vec();
         Ok(())
     }
 
-    fn new_order_v3(&self, ctx: &mut Context, _ix: &mut NewOrderInstructionV3) -> ProgramResult {
-        self.thaw_lp_token_cpi(ctx);
-        self.freeze_lp_token_cpi(ctx);
-        ctx.accounts = ctx.accounts[13..].to_vec();
-        Ok(())
+    fn new_order_v3(&self, ctx: &mut Context, ix: &mut NewOrderInstructionV3) -> ProgramResult {
+        Self::check_lp_holder(ctx, ALLOWLIST_ACCOUNTS + 7)?;
+        let price_band = self.price_band(ctx)?;
+        match ix.side {
+            Side::Ask => self.thaw_unlocked_lp_token_cpi(ctx, PLACE_ORDERS)?,
+            Side::Bid => self.thaw_lp_token_cpi(ctx, PLACE_ORDERS)?,
+        }
+        self.freeze_lp_token_cpi(ctx, PLACE_ORDERS)?;
+        ctx.accounts = ctx.accounts[CREDIX_ACCOUNTS..].to_vec();
+        price_band.new_order_v3(ctx, ix)
     }
 
     fn cancel_order_v2(
@@ -172,9 +373,10 @@ impl MarketMiddleware for CredixPermissionedMarket {
         ctx: &mut Context,
         _ix: &mut CancelOrderInstructionV2,
     ) -> ProgramResult {
-        self.thaw_lp_token_cpi(ctx);
-        self.freeze_lp_token_cpi(ctx);
-        ctx.accounts = ctx.accounts[13..].to_vec();
+        Self::check_lp_holder(ctx, 4)?;
+        self.thaw_lp_token_cpi(ctx, CANCEL_ORDERS)?;
+        self.freeze_lp_token_cpi(ctx, CANCEL_ORDERS)?;
+        ctx.accounts = ctx.accounts[CREDIX_ACCOUNTS..].to_vec();
         Ok(())
     }
 
@@ -183,23 +385,26 @@ impl MarketMiddleware for CredixPermissionedMarket {
         ctx: &mut Context,
         _client_id: &mut u64,
     ) -> ProgramResult {
-        self.thaw_lp_token_cpi(ctx);
-        self.freeze_lp_token_cpi(ctx);
-        ctx.accounts = ctx.accounts[13..].to_vec();
+        Self::check_lp_holder(ctx, 4)?;
+        self.thaw_lp_token_cpi(ctx, CANCEL_ORDERS)?;
+        self.freeze_lp_token_cpi(ctx, CANCEL_ORDERS)?;
+        ctx.accounts = ctx.accounts[CREDIX_ACCOUNTS..].to_vec();
         Ok(())
     }
 
     fn settle_funds(&self, ctx: &mut Context) -> ProgramResult {
-        self.thaw_lp_token_cpi(ctx);
-        self.freeze_lp_token_cpi(ctx);
-        ctx.accounts = ctx.accounts[13..].to_vec();
+        Self::check_lp_holder(ctx, 2)?;
+        self.thaw_lp_token_cpi(ctx, OWNER_ONLY)?;
+        self.freeze_lp_token_cpi(ctx, OWNER_ONLY)?;
+        ctx.accounts = ctx.accounts[CREDIX_ACCOUNTS..].to_vec();
         Ok(())
     }
 
     fn close_open_orders(&self, ctx: &mut Context) -> ProgramResult {
-        self.thaw_lp_token_cpi(ctx);
-        self.freeze_lp_token_cpi(ctx);
-        ctx.accounts = ctx.accounts[13..].to_vec();
+        Self::check_lp_holder(ctx, 1)?;
+        self.thaw_lp_token_cpi(ctx, OWNER_ONLY)?;
+        self.freeze_lp_token_cpi(ctx, OWNER_ONLY)?;
+        ctx.accounts = ctx.accounts[CREDIX_ACCOUNTS..].to_vec();
         Ok(())
     }
 
@@ -246,6 +451,22 @@ pub enum ErrorCode {
     TokenNotRevoked,
     #[msg("Required cpi accounts and variables not found")]
     MissingRequiredCpiAccounts,
+    #[msg("Market coin mint is not the LP token mint of the global market state")]
+    MarketMintMismatch,
+    #[msg("The Signer is not authorized to use this instruction")]
+    UnauthorizedSigner,
+    #[msg("Allowlist account does not belong to the market")]
+    InvalidAllowlist,
+    #[msg("User is not on the allowlist of the market")]
+    NotAllowlisted,
+    #[msg("Allowlist still has entries")]
+    AllowlistNotEmpty,
+    #[msg("LP token holder is not the owner of the open orders account")]
+    InvalidLpHolder,
+    #[msg("Overflow")]
+    Overflow,
+    #[msg("Underflow")]
+    Underflow,
 }
 
 // Constants.
@@ -253,3 +474,8 @@ pub mod referral {
     use anchor_lang::prelude::*;
     declare_id!("EoYuxcwTfyznBF2ebzZ8McqvveyxtMNTGAXGmNKycchB");
 }
+
+pub mod dex {
+    use anchor_lang::prelude::*;
+    declare_id!("A3KCE92wXZMtGGJT6XYL2KHva58VXvWkhcqfJ6Q5JEia");
+}
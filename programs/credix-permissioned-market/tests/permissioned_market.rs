@@ -0,0 +1,891 @@
+//! End to end tests of the credix permissioned market against the serum dex.
+//!
+//! Native programs can't resize accounts in a CPI, which anchor's `init`
+//! does, so the credix and proxy programs run as BPF programs built by
+//! `anchor build`:
+//!
+//! ```sh
+//! anchor build
+//! cargo test-bpf --manifest-path programs/credix-permissioned-market/Cargo.toml
+//! ```
+#![cfg(feature = "test-bpf")]
+
+use anchor_lang::prelude::{ProgramError, Pubkey, Rent};
+
+use credix_client::{
+    find_open_orders_init_authority, find_trading_delegate, CredixMarket, MarketAccounts,
+    PermissionedMarket,
+};
+use serum_dex_permissioned::serum_dex;
+use serum_dex_permissioned::serum_dex::instruction::{
+    CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior,
+};
+use serum_dex_permissioned::serum_dex::matching::{OrderType, Side};
+use serum_dex_permissioned::serum_dex::state::{
+    gen_vault_signer_key, MarketState, OpenOrders, ToAlignedBytes,
+};
+use serum_dex_permissioned::{CANCEL_ORDERS, PLACE_ORDERS};
+use solana_gateway::state::get_gatekeeper_address_with_seed;
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_sdk::transport::TransportError;
+use spl_token::state::{Account as TokenAccount, AccountState};
+use std::mem::size_of;
+use std::num::NonZeroU64;
+
+const GLOBAL_MARKET_SEED: &str = "credix-market";
+const DECIMALS: u8 = 6;
+const DEPOSIT: u64 = 1_000_000;
+const COIN_LOT_SIZE: u64 = 1_000;
+const PC_LOT_SIZE: u64 = 10;
+
+// Fees of the base fee tier, 22 and 3 bps of the quote amount.
+const TAKER_FEE_BPS: u64 = 22;
+const MAKER_REBATE_BPS: u64 = 3;
+
+// Padding of every serum account, b"serum" and b"padding".
+const SERUM_PADDING: usize = 12;
+
+struct TestMarket {
+    context: ProgramTestContext,
+    permissioned_market: PermissionedMarket,
+    user: Keypair,
+    user_base_account: Pubkey,
+    referral_base_account: Pubkey,
+    consume_events_authority: Keypair,
+}
+
+impl TestMarket {
+    /// Sets up a credix market, its LP token market on serum and a user with
+    /// a credix pass, a gateway token and LP tokens.
+    async fn new() -> Self {
+        let mut program_test = ProgramTest::default();
+        program_test.add_program("credix", credix::ID, None);
+        program_test.add_program(
+            "credix_permissioned_market",
+            credix_permissioned_market::ID,
+            None,
+        );
+        add_artifact(
+            &mut program_test,
+            "serum_dex",
+            credix_permissioned_market::dex::ID,
+        );
+
+        add_artifact(
+            &mut program_test,
+            "solana_gateway_program",
+            credix::gateway_program::ID,
+        );
+
+        let base_mint = Keypair::new();
+        let lp_token_mint = Keypair::new();
+        let gatekeeper_network = Keypair::new();
+
+        let mut context = program_test.start_with_context().await;
+        let payer = clone_keypair(&context.payer);
+
+        let credix_market = CredixMarket::new(
+            GLOBAL_MARKET_SEED,
+            gatekeeper_network.pubkey(),
+            base_mint.pubkey(),
+            lp_token_mint.pubkey(),
+        );
+
+        // Credix market, with the payer as gatekeeper of its network.
+        let mut ixs = create_mint(&context, &base_mint.pubkey(), &payer.pubkey()).await;
+        ixs.push(credix_market.initialize_market(&payer.pubkey()));
+        ixs.push(solana_gateway::instruction::add_gatekeeper(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            &gatekeeper_network.pubkey(),
+        ));
+        process(
+            &mut context,
+            &ixs,
+            &[&base_mint, &lp_token_mint, &gatekeeper_network],
+        )
+        .await
+        .unwrap();
+
+        // LP tokens of the user.
+        let (user, user_base_account) = create_user(&mut context, &credix_market, DEPOSIT).await;
+        let ix = credix_market.deposit_funds(&user.pubkey(), &user_base_account, DEPOSIT);
+        process(&mut context, &[ix], &[&user]).await.unwrap();
+
+        // Serum market of the LP token, with the proxy as open orders
+        // authority.
+        let dex_program_id = credix_permissioned_market::dex::ID;
+        let market = Keypair::new();
+        let request_queue = Keypair::new();
+        let event_queue = Keypair::new();
+        let bids = Keypair::new();
+        let asks = Keypair::new();
+        let coin_vault = Keypair::new();
+        let pc_vault = Keypair::new();
+        let (vault_signer_nonce, vault_signer) = (0..)
+            .find_map(|nonce| {
+                gen_vault_signer_key(nonce, &market.pubkey(), &dex_program_id)
+                    .ok()
+                    .map(|vault_signer| (nonce, vault_signer))
+            })
+            .unwrap();
+
+        let mut ixs = create_token_account(
+            &context,
+            &coin_vault.pubkey(),
+            &lp_token_mint.pubkey(),
+            &vault_signer,
+        )
+        .await;
+        ixs.extend(
+            create_token_account(
+                &context,
+                &pc_vault.pubkey(),
+                &base_mint.pubkey(),
+                &vault_signer,
+            )
+            .await,
+        );
+        process(&mut context, &ixs, &[&coin_vault, &pc_vault])
+            .await
+            .unwrap();
+
+        let mut ixs = Vec::new();
+        for (account, space) in [
+            (&market, size_of::<MarketState>() + SERUM_PADDING),
+            (&request_queue, 5120 + SERUM_PADDING),
+            (&event_queue, 262144 + SERUM_PADDING),
+            (&bids, 65536 + SERUM_PADDING),
+            (&asks, 65536 + SERUM_PADDING),
+        ] {
+            ixs.push(create_account(&context, &account.pubkey(), space, &dex_program_id).await);
+        }
+        process(
+            &mut context,
+            &ixs,
+            &[&market, &request_queue, &event_queue, &bids, &asks],
+        )
+        .await
+        .unwrap();
+
+        let (open_orders_authority, _) = find_open_orders_init_authority(
+            &credix_permissioned_market::ID,
+            &dex_program_id,
+            &market.pubkey(),
+        );
+        let prune_authority = Pubkey::new_unique();
+        let consume_events_authority = Keypair::new();
+        let ix = serum_dex::instruction::initialize_market(
+            &market.pubkey(),
+            &dex_program_id,
+            &lp_token_mint.pubkey(),
+            &base_mint.pubkey(),
+            &coin_vault.pubkey(),
+            &pc_vault.pubkey(),
+            Some(&open_orders_authority),
+            Some(&prune_authority),
+            Some(&consume_events_authority.pubkey()),
+            &bids.pubkey(),
+            &asks.pubkey(),
+            &request_queue.pubkey(),
+            &event_queue.pubkey(),
+            COIN_LOT_SIZE,
+            PC_LOT_SIZE,
+            vault_signer_nonce,
+            100,
+        )
+        .unwrap();
+        process(&mut context, &[ix], &[]).await.unwrap();
+
+        let permissioned_market = PermissionedMarket::new(
+            credix_market,
+            dex_program_id,
+            MarketAccounts {
+                market: market.pubkey(),
+                request_queue: request_queue.pubkey(),
+                event_queue: event_queue.pubkey(),
+                bids: bids.pubkey(),
+                asks: asks.pubkey(),
+                coin_vault: coin_vault.pubkey(),
+                pc_vault: pc_vault.pubkey(),
+                vault_signer,
+            },
+        );
+
+        // Signing authority of the proxy and referral of its settlements.
+        let referral_base_account = Keypair::new();
+        let mut ixs = vec![credix_client::create_signing_authority(
+            &credix_permissioned_market::ID,
+            &payer.pubkey(),
+        )];
+        ixs.extend(
+            create_token_account(
+                &context,
+                &referral_base_account.pubkey(),
+                &base_mint.pubkey(),
+                &credix_permissioned_market::referral::ID,
+            )
+            .await,
+        );
+        process(&mut context, &ixs, &[&referral_base_account])
+            .await
+            .unwrap();
+
+        Self {
+            context,
+            permissioned_market,
+            user,
+            user_base_account,
+            referral_base_account: referral_base_account.pubkey(),
+            consume_events_authority,
+        }
+    }
+
+    async fn token_account(&mut self, address: &Pubkey) -> TokenAccount {
+        let account = self
+            .context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+            .unwrap();
+        TokenAccount::unpack(&account.data).unwrap()
+    }
+
+    async fn lp_token_account(&mut self) -> TokenAccount {
+        let address = self
+            .permissioned_market
+            .credix
+            .lp_token_account(&self.user.pubkey());
+        self.token_account(&address).await
+    }
+
+    async fn assert_lp_tokens_frozen(&mut self) {
+        assert_eq!(self.lp_token_account().await.state, AccountState::Frozen);
+    }
+
+    async fn open_orders(&mut self, index: u8) -> OpenOrders {
+        let address = self
+            .permissioned_market
+            .open_orders(&self.user.pubkey(), index);
+        let account = self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        let data = &account.data[5..5 + size_of::<OpenOrders>()];
+        *bytemuck::from_bytes(data)
+    }
+
+    async fn send(&mut self, ix: Instruction) -> Result<(), TransportError> {
+        let user = clone_keypair(&self.user);
+        process(&mut self.context, &[ix], &[&user]).await
+    }
+
+    /// Sends an instruction signed by the owner of the credix market, the
+    /// payer of the validator.
+    async fn send_as_owner(&mut self, ix: Instruction) -> Result<(), TransportError> {
+        process(&mut self.context, &[ix], &[]).await
+    }
+
+    fn ask(&self, max_coin_lots: u64, client_order_id: u64) -> NewOrderInstructionV3 {
+        NewOrderInstructionV3 {
+            side: Side::Ask,
+            limit_price: NonZeroU64::new(1_000).unwrap(),
+            max_coin_qty: NonZeroU64::new(max_coin_lots).unwrap(),
+            max_native_pc_qty_including_fees: NonZeroU64::new(u64::MAX).unwrap(),
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            order_type: OrderType::Limit,
+            client_order_id,
+            limit: 10,
+        }
+    }
+}
+
+#[tokio::test]
+async fn deposit_freezes_lp_tokens() {
+    let mut market = TestMarket::new().await;
+
+    let lp_token_account = market.lp_token_account().await;
+    assert_eq!(lp_token_account.amount, DEPOSIT);
+    assert_eq!(lp_token_account.state, AccountState::Frozen);
+    let user_base_account = market.user_base_account;
+    assert_eq!(market.token_account(&user_base_account).await.amount, 0);
+}
+
+#[tokio::test]
+async fn place_cancel_and_settle_orders() {
+    let mut market = TestMarket::new().await;
+    let user = market.user.pubkey();
+    let lp_token_account = market.permissioned_market.credix.lp_token_account(&user);
+
+    let ix = market.permissioned_market.init_open_orders(&user, 0);
+    market.send(ix).await.unwrap();
+    market.assert_lp_tokens_frozen().await;
+
+    // Place an ask, locking its LP tokens in the coin vault.
+    let ix = market.permissioned_market.new_order_v3(
+        &user,
+        0,
+        &lp_token_account,
+        None,
+        market.ask(10, 1),
+    );
+    market.send(ix).await.unwrap();
+    market.assert_lp_tokens_frozen().await;
+    let locked = 10 * COIN_LOT_SIZE;
+    assert_eq!(market.lp_token_account().await.amount, DEPOSIT - locked);
+    let open_orders = market.open_orders(0).await;
+    assert_eq!({ open_orders.native_coin_total }, locked);
+
+    // Place and cancel a second ask.
+    let ix = market.permissioned_market.new_order_v3(
+        &user,
+        0,
+        &lp_token_account,
+        None,
+        market.ask(5, 2),
+    );
+    market.send(ix).await.unwrap();
+    market.assert_lp_tokens_frozen().await;
+    let ix = market
+        .permissioned_market
+        .cancel_order_by_client_id_v2(&user, 0, None, 2);
+    market.send(ix).await.unwrap();
+    market.assert_lp_tokens_frozen().await;
+
+    // Cancel the first ask by its order id.
+    let open_orders = market.open_orders(0).await;
+    let (client_order_ids, orders) = (open_orders.client_order_ids, open_orders.orders);
+    let slot = client_order_ids.iter().position(|id| *id == 1).unwrap();
+    let ix = market.permissioned_market.cancel_order_v2(
+        &user,
+        0,
+        None,
+        CancelOrderInstructionV2 {
+            side: Side::Ask,
+            order_id: orders[slot],
+        },
+    );
+    market.send(ix).await.unwrap();
+    market.assert_lp_tokens_frozen().await;
+    let open_orders = market.open_orders(0).await;
+    assert_eq!({ open_orders.native_coin_free }, 15 * COIN_LOT_SIZE);
+
+    // Settle the cancelled orders back into the LP token account.
+    let (user_base_account, referral_base_account) =
+        (market.user_base_account, market.referral_base_account);
+    let ix = market.permissioned_market.settle_funds(
+        &user,
+        0,
+        &lp_token_account,
+        &user_base_account,
+        &referral_base_account,
+    );
+    market.send(ix).await.unwrap();
+    market.assert_lp_tokens_frozen().await;
+    assert_eq!(market.lp_token_account().await.amount, DEPOSIT);
+    let open_orders = market.open_orders(0).await;
+    assert_eq!({ open_orders.native_coin_total }, 0);
+}
+
+#[tokio::test]
+async fn orders_require_the_owner_signature() {
+    let mut market = TestMarket::new().await;
+    let user = market.user.pubkey();
+    let ix = market.permissioned_market.init_open_orders(&user, 0);
+    market.send(ix).await.unwrap();
+
+    let lp_token_account = market.permissioned_market.credix.lp_token_account(&user);
+    let mut ix = market.permissioned_market.new_order_v3(
+        &user,
+        0,
+        &lp_token_account,
+        None,
+        market.ask(10, 1),
+    );
+    for account in ix.accounts.iter_mut() {
+        account.is_signer = false;
+    }
+    let payer = clone_keypair(&market.context.payer);
+    assert!(process(&mut market.context, &[ix], &[&payer])
+        .await
+        .is_err());
+    market.assert_lp_tokens_frozen().await;
+}
+
+#[tokio::test]
+async fn allowlisted_markets_take_orders_of_listed_users() {
+    let mut market = TestMarket::new().await;
+    let user = market.user.pubkey();
+    let owner = market.context.payer.pubkey();
+    let lp_token_account = market.permissioned_market.credix.lp_token_account(&user);
+    let ix = market.permissioned_market.init_open_orders(&user, 0);
+    market.send(ix).await.unwrap();
+
+    // Only the owner of the credix market creates allowlists.
+    let ix = market.permissioned_market.create_allowlist(&user);
+    let err = market.send(ix).await.unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        credix_permissioned_market::ErrorCode::UnauthorizedSigner.into()
+    );
+    let ix = market.permissioned_market.create_allowlist(&owner);
+    market.send_as_owner(ix).await.unwrap();
+
+    let ask = |market: &TestMarket, client_order_id| {
+        market.permissioned_market.new_order_v3(
+            &user,
+            0,
+            &lp_token_account,
+            None,
+            market.ask(1, client_order_id),
+        )
+    };
+    let ix = ask(&market, 1);
+    let err = market.send(ix).await.unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        credix_permissioned_market::ErrorCode::NotAllowlisted.into()
+    );
+
+    let ix = market.permissioned_market.add_to_allowlist(&owner, &user);
+    market.send_as_owner(ix).await.unwrap();
+    let ix = ask(&market, 2);
+    market.send(ix).await.unwrap();
+    market.assert_lp_tokens_frozen().await;
+
+    // Entries must be removed before the allowlist is closed, or they would
+    // apply to the next allowlist of the market.
+    let ix = market.permissioned_market.close_allowlist(&owner);
+    let err = market.send_as_owner(ix).await.unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        credix_permissioned_market::ErrorCode::AllowlistNotEmpty.into()
+    );
+    let ix = market
+        .permissioned_market
+        .remove_from_allowlist(&owner, &user);
+    market.send_as_owner(ix).await.unwrap();
+    let ix = ask(&market, 3);
+    let err = market.send(ix).await.unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        credix_permissioned_market::ErrorCode::NotAllowlisted.into()
+    );
+
+    let ix = market.permissioned_market.close_allowlist(&owner);
+    market.send_as_owner(ix).await.unwrap();
+    let ix = ask(&market, 4);
+    market.send(ix).await.unwrap();
+}
+
+#[tokio::test]
+async fn allowlists_are_created_for_markets_of_allowed_dex_programs() {
+    let mut market = TestMarket::new().await;
+    let owner = market.context.payer.pubkey();
+
+    // A copy of the market, owned by another program.
+    let address = market.permissioned_market.accounts.market;
+    let mut account = market
+        .context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap();
+    account.owner = Pubkey::new_unique();
+    let fake_market = Pubkey::new_unique();
+    market.context.set_account(&fake_market, &account.into());
+
+    let mut permissioned_market = market.permissioned_market.clone();
+    permissioned_market.accounts.market = fake_market;
+    let ix = permissioned_market.create_allowlist(&owner);
+    let err = market.send_as_owner(ix).await.unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        serum_dex_permissioned::ErrorCode::InvalidTargetProgram.into()
+    );
+}
+
+#[tokio::test]
+async fn trading_delegates_place_and_cancel_orders() {
+    let mut market = TestMarket::new().await;
+    let user = market.user.pubkey();
+    let delegate = Keypair::new();
+    let lp_token_account = market.permissioned_market.credix.lp_token_account(&user);
+    let open_orders = market.permissioned_market.open_orders(&user, 0);
+    let ix = market.permissioned_market.init_open_orders(&user, 0);
+    market.send(ix).await.unwrap();
+
+    // Asks of the delegate are paid with a standing approval of the frozen
+    // LP tokens.
+    let locked = 10 * COIN_LOT_SIZE;
+    let ixs = [
+        market.permissioned_market.set_trading_delegate(
+            &user,
+            delegate.pubkey(),
+            PLACE_ORDERS | CANCEL_ORDERS,
+            0,
+        ),
+        market
+            .permissioned_market
+            .credix
+            .approve_lp_tokens(&user, &open_orders, locked),
+    ];
+    let user_keypair = clone_keypair(&market.user);
+    process(&mut market.context, &ixs, &[&user_keypair])
+        .await
+        .unwrap();
+    market.assert_lp_tokens_frozen().await;
+
+    let ix = market.permissioned_market.new_order_v3(
+        &user,
+        0,
+        &lp_token_account,
+        Some(&delegate.pubkey()),
+        market.ask(10, 1),
+    );
+    process(&mut market.context, &[ix], &[&delegate])
+        .await
+        .unwrap();
+    market.assert_lp_tokens_frozen().await;
+    assert_eq!(market.lp_token_account().await.amount, DEPOSIT - locked);
+
+    let ix = market.permissioned_market.cancel_order_by_client_id_v2(
+        &user,
+        0,
+        Some(&delegate.pubkey()),
+        1,
+    );
+    process(&mut market.context, &[ix], &[&delegate])
+        .await
+        .unwrap();
+    market.assert_lp_tokens_frozen().await;
+    assert_eq!({ market.open_orders(0).await.native_coin_free }, locked);
+
+    // Only the owner revokes its delegate.
+    let mut ix = market.permissioned_market.revoke_trading_delegate(&user);
+    ix.accounts[0].pubkey = delegate.pubkey();
+    let err = process(&mut market.context, &[ix], &[&delegate])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        credix_permissioned_market::ErrorCode::UnauthorizedSigner.into()
+    );
+
+    let ix = market.permissioned_market.revoke_trading_delegate(&user);
+    market.send(ix).await.unwrap();
+    let ix = market.permissioned_market.new_order_v3(
+        &user,
+        0,
+        &lp_token_account,
+        Some(&delegate.pubkey()),
+        market.ask(1, 2),
+    );
+    let err = process(&mut market.context, &[ix], &[&delegate])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        serum_dex_permissioned::ErrorCode::InvalidDelegate.into()
+    );
+}
+
+#[tokio::test]
+async fn delegates_of_other_holders_are_rejected() {
+    let mut market = TestMarket::new().await;
+    let user = market.user.pubkey();
+    let ix = market.permissioned_market.init_open_orders(&user, 0);
+    market.send(ix).await.unwrap();
+
+    // An attacker delegating to itself can't trade the open orders of the
+    // user with its own record.
+    let attacker = Keypair::new();
+    let ix = market.permissioned_market.set_trading_delegate(
+        &attacker.pubkey(),
+        attacker.pubkey(),
+        CANCEL_ORDERS,
+        0,
+    );
+    process(&mut market.context, &[ix], &[&attacker])
+        .await
+        .unwrap();
+
+    let mut ix = market.permissioned_market.cancel_order_by_client_id_v2(
+        &user,
+        0,
+        Some(&attacker.pubkey()),
+        1,
+    );
+    let record = ix.accounts.len() - 2;
+    ix.accounts[record].pubkey =
+        find_trading_delegate(&credix_permissioned_market::ID, &attacker.pubkey()).0;
+    let err = process(&mut market.context, &[ix], &[&attacker])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        serum_dex_permissioned::ErrorCode::InvalidDelegate.into()
+    );
+    market.assert_lp_tokens_frozen().await;
+}
+
+#[tokio::test]
+async fn crossing_orders_settle_into_the_wallets() {
+    let mut market = TestMarket::new().await;
+    let seller = market.user.pubkey();
+    let seller_lp_account = market.permissioned_market.credix.lp_token_account(&seller);
+    let (buyer, buyer_base_account) = create_user(
+        &mut market.context,
+        &market.permissioned_market.credix,
+        DEPOSIT,
+    )
+    .await;
+    let buyer_lp_account = market
+        .permissioned_market
+        .credix
+        .lp_token_account(&buyer.pubkey());
+
+    let ix = market.permissioned_market.init_open_orders(&seller, 0);
+    market.send(ix).await.unwrap();
+    let ix = market
+        .permissioned_market
+        .init_open_orders(&buyer.pubkey(), 0);
+    process(&mut market.context, &[ix], &[&buyer])
+        .await
+        .unwrap();
+
+    // The ask rests on the book and the bid takes all of it at its price.
+    let ix = market.permissioned_market.new_order_v3(
+        &seller,
+        0,
+        &seller_lp_account,
+        None,
+        market.ask(10, 1),
+    );
+    market.send(ix).await.unwrap();
+    let bid = NewOrderInstructionV3 {
+        side: Side::Bid,
+        max_native_pc_qty_including_fees: NonZeroU64::new(DEPOSIT).unwrap(),
+        ..market.ask(10, 2)
+    };
+    let ix =
+        market
+            .permissioned_market
+            .new_order_v3(&buyer.pubkey(), 0, &buyer_base_account, None, bid);
+    process(&mut market.context, &[ix], &[&buyer])
+        .await
+        .unwrap();
+
+    // The fill of the maker is only credited once its event is consumed.
+    let mut open_orders = vec![
+        market.permissioned_market.open_orders(&seller, 0),
+        market.permissioned_market.open_orders(&buyer.pubkey(), 0),
+    ];
+    open_orders.sort_by_key(|address| address.to_aligned_bytes());
+    let consume_events_authority = clone_keypair(&market.consume_events_authority);
+    let ix = market.permissioned_market.consume_events_permissioned(
+        &open_orders,
+        &consume_events_authority.pubkey(),
+        10,
+    );
+    process(&mut market.context, &[ix], &[&consume_events_authority])
+        .await
+        .unwrap();
+
+    let (seller_base_account, referral_base_account) =
+        (market.user_base_account, market.referral_base_account);
+    let ix = market.permissioned_market.settle_funds(
+        &seller,
+        0,
+        &seller_lp_account,
+        &seller_base_account,
+        &referral_base_account,
+    );
+    market.send(ix).await.unwrap();
+    let ix = market.permissioned_market.settle_funds(
+        &buyer.pubkey(),
+        0,
+        &buyer_lp_account,
+        &buyer_base_account,
+        &referral_base_account,
+    );
+    process(&mut market.context, &[ix], &[&buyer])
+        .await
+        .unwrap();
+
+    let traded = 10 * COIN_LOT_SIZE;
+    let quote = 10 * 1_000 * PC_LOT_SIZE;
+    let taker_fee = quote * TAKER_FEE_BPS / 10_000;
+    let maker_rebate = quote * MAKER_REBATE_BPS / 10_000;
+
+    let seller_lp = market.token_account(&seller_lp_account).await;
+    assert_eq!(seller_lp.amount, DEPOSIT - traded);
+    assert_eq!(seller_lp.state, AccountState::Frozen);
+    let buyer_lp = market.token_account(&buyer_lp_account).await;
+    assert_eq!(buyer_lp.amount, traded);
+    assert_eq!(buyer_lp.state, AccountState::Frozen);
+    assert_eq!(
+        market.token_account(&seller_base_account).await.amount,
+        quote + maker_rebate
+    );
+    assert_eq!(
+        market.token_account(&buyer_base_account).await.amount,
+        DEPOSIT - quote - taker_fee
+    );
+    // A fifth of the taker fee goes to the referral of the proxy.
+    assert_eq!(
+        market.token_account(&referral_base_account).await.amount,
+        taker_fee / 5
+    );
+    let open_orders = market.open_orders(0).await;
+    assert_eq!({ open_orders.native_coin_total }, 0);
+    assert_eq!({ open_orders.native_pc_total }, 0);
+}
+
+/// Loads a program of `artifacts`, deployed at genesis by `anchor test` too.
+fn add_artifact(program_test: &mut ProgramTest, name: &str, program_id: Pubkey) {
+    let path = format!("{}/../../artifacts/{}.so", env!("CARGO_MANIFEST_DIR"), name);
+    let data = std::fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    program_test.add_account(
+        program_id,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: solana_sdk::bpf_loader::id(),
+            executable: true,
+            rent_epoch: 0,
+        },
+    );
+}
+
+/// Creates a user with `base_amount` base tokens, a credix pass and a
+/// gateway token issued by the payer, the gatekeeper of the market.
+async fn create_user(
+    context: &mut ProgramTestContext,
+    credix_market: &CredixMarket,
+    base_amount: u64,
+) -> (Keypair, Pubkey) {
+    let payer = context.payer.pubkey();
+    let user = Keypair::new();
+    let base_account = Keypair::new();
+    let (gatekeeper_account, _) =
+        get_gatekeeper_address_with_seed(&payer, &credix_market.gatekeeper_network);
+
+    let mut ixs = vec![system_instruction::transfer(
+        &payer,
+        &user.pubkey(),
+        10_000_000_000,
+    )];
+    ixs.extend(
+        create_token_account(
+            context,
+            &base_account.pubkey(),
+            &credix_market.base_mint,
+            &user.pubkey(),
+        )
+        .await,
+    );
+    ixs.push(
+        spl_token::instruction::mint_to(
+            &spl_token::ID,
+            &credix_market.base_mint,
+            &base_account.pubkey(),
+            &payer,
+            &[],
+            base_amount,
+        )
+        .unwrap(),
+    );
+    ixs.push(credix_market.create_credix_pass(&payer, &user.pubkey()));
+    ixs.push(solana_gateway::instruction::issue_vanilla(
+        &payer,
+        &user.pubkey(),
+        &gatekeeper_account,
+        &payer,
+        &credix_market.gatekeeper_network,
+        None,
+        None,
+    ));
+    process(context, &ixs, &[&base_account]).await.unwrap();
+    (user, base_account.pubkey())
+}
+
+async fn process(
+    context: &mut ProgramTestContext,
+    ixs: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let payer = clone_keypair(&context.payer);
+    let mut all_signers = vec![&payer];
+    all_signers.extend(signers);
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction =
+        Transaction::new_signed_with_payer(ixs, Some(&payer.pubkey()), &all_signers, blockhash);
+    context.banks_client.process_transaction(transaction).await
+}
+
+async fn create_account(
+    context: &ProgramTestContext,
+    address: &Pubkey,
+    space: usize,
+    owner: &Pubkey,
+) -> Instruction {
+    let rent = context.banks_client.clone().get_rent().await.unwrap();
+    system_instruction::create_account(
+        &context.payer.pubkey(),
+        address,
+        rent.minimum_balance(space),
+        space as u64,
+        owner,
+    )
+}
+
+async fn create_mint(
+    context: &ProgramTestContext,
+    mint: &Pubkey,
+    authority: &Pubkey,
+) -> Vec<Instruction> {
+    vec![
+        create_account(context, mint, spl_token::state::Mint::LEN, &spl_token::ID).await,
+        spl_token::instruction::initialize_mint(&spl_token::ID, mint, authority, None, DECIMALS)
+            .unwrap(),
+    ]
+}
+
+async fn create_token_account(
+    context: &ProgramTestContext,
+    account: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Vec<Instruction> {
+    vec![
+        create_account(context, account, TokenAccount::LEN, &spl_token::ID).await,
+        spl_token::instruction::initialize_account(&spl_token::ID, account, mint, owner).unwrap(),
+    ]
+}
+
+/// Custom program error of a failed transaction.
+fn custom_error(err: TransportError) -> ProgramError {
+    match err {
+        TransportError::TransactionError(TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(code),
+        )) => ProgramError::Custom(code),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+fn clone_keypair(keypair: &Keypair) -> Keypair {
+    Keypair::from_bytes(&keypair.to_bytes()).unwrap()
+}
@@ -5,7 +5,6 @@ use anchor_spl::{
     token::{Mint, TokenAccount},
 };
 use spl_token::{self, solana_program::system_program};
-use std::mem::size_of;
 
 use crate::errors::ErrorCode;
 use crate::*;
@@ -21,7 +20,7 @@ pub struct InitializeMarket<'info> {
         payer = owner,
         seeds = [global_market_seed.as_bytes()],
         bump = global_market_state_bump,
-        space = 2 * size_of::<GlobalMarketState>() + 8,
+        space = GlobalMarketState::LEN,
     )]
     pub global_market_state: Box<Account<'info, GlobalMarketState>>,
     #[account(
@@ -97,6 +96,7 @@ pub struct DepositFunds<'info> {
     )]
     pub investor_lp_token_account: Account<'info, TokenAccount>,
     #[account(
+        mut,
         seeds = [global_market_state.key().as_ref(), investor.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
         bump,
         constraint = credix_pass.active @ ErrorCode::CredixPassInactive,
@@ -125,7 +125,7 @@ pub struct CreateCredixPass<'info> {
         seeds = [global_market_state.key().as_ref(), pass_holder.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
         bump = pass_bump,
         payer = owner,
-        space = 2 * size_of::<CredixPass>() + 8,
+        space = CredixPass::LEN,
     )]
     pub credix_pass: Account<'info, CredixPass>,
     pub global_market_state: Account<'info, GlobalMarketState>,
@@ -149,6 +149,333 @@ pub struct UpdateCredixPass<'info> {
     pub global_market_state: Account<'info, GlobalMarketState>,
 }
 
+#[derive(Accounts)]
+pub struct CloseCredixPass<'info> {
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+    pub pass_holder: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [global_market_state.key().as_ref(), pass_holder.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
+        bump,
+        close = owner,
+    )]
+    pub credix_pass: Account<'info, CredixPass>,
+    #[account(has_one = owner @ ErrorCode::UnauthorizedSigner)]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePriceBand<'info> {
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::UnauthorizedSigner,
+    )]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDepositCap<'info> {
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::UnauthorizedSigner,
+    )]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateInvestorDepositCap<'info> {
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+    pub pass_holder: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [global_market_state.key().as_ref(), pass_holder.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
+        bump,
+    )]
+    pub credix_pass: Account<'info, CredixPass>,
+    #[account(has_one = owner @ ErrorCode::UnauthorizedSigner)]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLpLockup<'info> {
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::UnauthorizedSigner,
+    )]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateWithdrawEpochs<'info> {
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::UnauthorizedSigner,
+    )]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64)]
+pub struct CreateWithdrawRequest<'info> {
+    #[account(mut, signer)]
+    pub investor: AccountInfo<'info>,
+    #[account(mut)]
+    pub global_market_state: Box<Account<'info, GlobalMarketState>>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump = global_market_state.signing_authority_bump
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = investor,
+        seeds = [global_market_state.key().as_ref(), investor.key.as_ref(), WITHDRAW_REQUEST_SEED.as_bytes()],
+        bump,
+        space = WithdrawRequest::LEN,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+    #[account(
+        seeds = [global_market_state.key().as_ref(), investor.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
+        bump = credix_pass.bump,
+    )]
+    pub credix_pass: Box<Account<'info, CredixPass>>,
+    #[account(
+        init_if_needed,
+        payer = investor,
+        seeds = [withdraw_request.key().as_ref(), WITHDRAW_ESCROW_SEED.as_bytes()],
+        bump,
+        token::mint = lp_token_mint_account,
+        token::authority = signing_authority,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = lp_token_mint_account,
+        associated_token::authority = investor,
+        constraint = investor_lp_token_account.amount >= amount @ ErrorCode::NotEnoughLPTokens
+    )]
+    pub investor_lp_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(address = global_market_state.lp_token_mint_account)]
+    pub lp_token_mint_account: Box<Account<'info, Mint>>,
+    #[account[address = rent::ID]]
+    pub rent: Sysvar<'info, Rent>,
+    #[account(address = spl_token::ID)]
+    pub token_program: AccountInfo<'info>,
+    #[account(address = system_program::ID)]
+    pub system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseWithdrawEpoch<'info> {
+    #[account(mut)]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump = global_market_state.signing_authority_bump
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    #[account(
+        associated_token::mint = global_market_state.liquidity_pool_token_mint_account,
+        associated_token::authority = signing_authority,
+    )]
+    pub liquidity_pool_token_account: Account<'info, TokenAccount>,
+    #[account(address = global_market_state.lp_token_mint_account)]
+    pub lp_token_mint_account: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct FillWithdrawRequest<'info> {
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    #[account(mut)]
+    pub global_market_state: Box<Account<'info, GlobalMarketState>>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump = global_market_state.signing_authority_bump
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [global_market_state.key().as_ref(), withdraw_request.investor.as_ref(), WITHDRAW_REQUEST_SEED.as_bytes()],
+        bump,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+    #[account(
+        mut,
+        seeds = [withdraw_request.key().as_ref(), WITHDRAW_ESCROW_SEED.as_bytes()],
+        bump,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+    #[account(address = withdraw_request.investor)]
+    pub investor: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [global_market_state.key().as_ref(), investor.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
+        bump = credix_pass.bump,
+    )]
+    pub credix_pass: Box<Account<'info, CredixPass>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = base_mint_account,
+        associated_token::authority = investor
+    )]
+    pub investor_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = global_market_state.liquidity_pool_token_mint_account,
+        associated_token::authority = signing_authority,
+    )]
+    pub liquidity_pool_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        address = global_market_state.lp_token_mint_account,
+    )]
+    pub lp_token_mint_account: Box<Account<'info, Mint>>,
+    #[account(address = global_market_state.liquidity_pool_token_mint_account)]
+    pub base_mint_account: AccountInfo<'info>,
+    #[account(address = associated_token::ID)]
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    #[account[address = rent::ID]]
+    pub rent: Sysvar<'info, Rent>,
+    #[account(address = spl_token::ID)]
+    pub token_program: AccountInfo<'info>,
+    #[account(address = system_program::ID)]
+    pub system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelWithdrawRequest<'info> {
+    #[account(signer)]
+    pub investor: AccountInfo<'info>,
+    #[account(mut)]
+    pub global_market_state: Box<Account<'info, GlobalMarketState>>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump = global_market_state.signing_authority_bump
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [global_market_state.key().as_ref(), investor.key.as_ref(), WITHDRAW_REQUEST_SEED.as_bytes()],
+        bump,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+    #[account(
+        mut,
+        seeds = [withdraw_request.key().as_ref(), WITHDRAW_ESCROW_SEED.as_bytes()],
+        bump,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = lp_token_mint_account,
+        associated_token::authority = investor,
+    )]
+    pub investor_lp_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(address = global_market_state.lp_token_mint_account)]
+    pub lp_token_mint_account: Box<Account<'info, Mint>>,
+    #[account(address = spl_token::ID)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RefundWithdrawRequest<'info> {
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::UnauthorizedSigner,
+    )]
+    pub global_market_state: Box<Account<'info, GlobalMarketState>>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump = global_market_state.signing_authority_bump
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    pub investor: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [global_market_state.key().as_ref(), investor.key.as_ref(), WITHDRAW_REQUEST_SEED.as_bytes()],
+        bump,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+    #[account(
+        mut,
+        seeds = [withdraw_request.key().as_ref(), WITHDRAW_ESCROW_SEED.as_bytes()],
+        bump,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = lp_token_mint_account,
+        associated_token::authority = investor,
+    )]
+    pub investor_lp_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(address = global_market_state.lp_token_mint_account)]
+    pub lp_token_mint_account: Box<Account<'info, Mint>>,
+    #[account(address = spl_token::ID)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGlobalMarketState<'info> {
+    // Markets created before their owner was recorded have no owner to sign,
+    // the upgrade authority of the program migrates them.
+    #[account(mut, signer)]
+    pub upgrade_authority: AccountInfo<'info>,
+    #[account(
+        address = util::program_data_address(),
+        constraint = program_data.upgrade_authority_address == Some(upgrade_authority.key())
+            @ ErrorCode::UnauthorizedSigner,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    // Recorded on markets without an owner, must be the recorded one
+    // otherwise.
+    pub owner: AccountInfo<'info>,
+    // Older layouts only deserialize once grown.
+    #[account(mut, owner = crate::ID)]
+    pub global_market_state: AccountInfo<'info>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump,
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    // Its balance is the value of the pool of markets that didn't track it.
+    pub liquidity_pool_token_account: Account<'info, TokenAccount>,
+    #[account(address = system_program::ID)]
+    pub system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateCredixPass<'info> {
+    #[account(mut, signer)]
+    pub owner: AccountInfo<'info>,
+    pub pass_holder: AccountInfo<'info>,
+    // Older layouts only deserialize once grown.
+    #[account(
+        mut,
+        seeds = [global_market_state.key().as_ref(), pass_holder.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
+        bump,
+        owner = crate::ID,
+    )]
+    pub credix_pass: AccountInfo<'info>,
+    #[account(has_one = owner @ ErrorCode::UnauthorizedSigner)]
+    pub global_market_state: Account<'info, GlobalMarketState>,
+    #[account(address = system_program::ID)]
+    pub system_program: AccountInfo<'info>,
+}
+
 #[derive(Accounts)]
 pub struct FreezeThawLpTokens<'info> {
     #[account(
@@ -156,7 +483,7 @@ pub struct FreezeThawLpTokens<'info> {
         constraint = credix_permissioned_pda.owner == &permissioned_market_program::ID,
     )]
     pub credix_permissioned_pda: AccountInfo<'info>,
-    #[account(signer, mut)]
+    #[account(mut, signer)]
     pub lp_holder: AccountInfo<'info>,
     #[account(
         seeds = [global_market_state.key().as_ref(), lp_holder.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
@@ -194,3 +521,77 @@ pub struct FreezeThawLpTokens<'info> {
     )]
     pub gateway_token: AccountInfo<'info>,
 }
+
+/// Freezes and thaws the LP tokens of a holder trading through a trading
+/// delegate registered on the permissioned market.
+#[derive(Accounts)]
+pub struct FreezeThawDelegatedLpTokens<'info> {
+    #[account(
+        signer,
+        constraint = credix_permissioned_pda.owner == &permissioned_market_program::ID,
+    )]
+    pub credix_permissioned_pda: AccountInfo<'info>,
+    pub lp_holder: AccountInfo<'info>,
+    pub trading_delegate: AccountInfo<'info>,
+    #[account(signer)]
+    pub delegate: AccountInfo<'info>,
+    #[account(
+        seeds = [global_market_state.key().as_ref(), lp_holder.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
+        bump,
+        constraint = credix_pass.active @ ErrorCode::CredixPassInactive,
+    )]
+    pub credix_pass: Account<'info, CredixPass>,
+    #[account(
+        mut,
+        associated_token::mint = lp_token_mint_account,
+        associated_token::authority = lp_holder
+    )]
+    pub lp_token_account: Account<'info, TokenAccount>,
+    pub global_market_state: Account<'info, GlobalMarketState>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump = global_market_state.signing_authority_bump
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    #[account(
+        address = global_market_state.lp_token_mint_account,
+    )]
+    pub lp_token_mint_account: Account<'info, Mint>,
+    #[account(address = spl_token::ID)]
+    pub token_program: AccountInfo<'info>,
+    #[account(
+        constraint = gateway_token.owner == &gateway_program::ID
+    )]
+    pub gateway_token: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveLpTokens<'info> {
+    #[account(signer)]
+    pub lp_holder: AccountInfo<'info>,
+    pub delegate: AccountInfo<'info>,
+    #[account(
+        seeds = [global_market_state.key().as_ref(), lp_holder.key.as_ref(), CREDIX_PASS_SEED.as_bytes()],
+        bump,
+        constraint = credix_pass.active @ ErrorCode::CredixPassInactive,
+    )]
+    pub credix_pass: Account<'info, CredixPass>,
+    #[account(
+        mut,
+        associated_token::mint = lp_token_mint_account,
+        associated_token::authority = lp_holder
+    )]
+    pub lp_token_account: Account<'info, TokenAccount>,
+    pub global_market_state: Account<'info, GlobalMarketState>,
+    #[account(
+        seeds = [global_market_state.key().as_ref()],
+        bump = global_market_state.signing_authority_bump
+    )]
+    pub signing_authority: AccountInfo<'info>,
+    #[account(
+        address = global_market_state.lp_token_mint_account,
+    )]
+    pub lp_token_mint_account: Account<'info, Mint>,
+    #[account(address = spl_token::ID)]
+    pub token_program: AccountInfo<'info>,
+}
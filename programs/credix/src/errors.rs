@@ -24,4 +24,36 @@ pub enum ErrorCode {
     NotEnoughLPTokens,
     #[msg("Not enough Base tokens.")]
     NotEnoughBaseTokens,
+    #[msg("The account has a newer layout than the program supports.")]
+    UnsupportedAccountVersion,
+    #[msg("The deposit exceeds the deposit cap of the pool.")]
+    PoolDepositCapExceeded,
+    #[msg("The deposit exceeds the deposit cap of the investor.")]
+    InvestorDepositCapExceeded,
+    #[msg("Withdrawal epochs are not configured.")]
+    WithdrawEpochsDisabled,
+    #[msg("The withdrawal epoch is not over yet.")]
+    WithdrawEpochNotOver,
+    #[msg("Requests of the previous withdrawal epoch are not filled yet.")]
+    WithdrawRequestsNotFilled,
+    #[msg("The investor already has a pending withdrawal request.")]
+    WithdrawRequestPending,
+    #[msg("The withdrawal request is not due.")]
+    WithdrawRequestNotDue,
+    #[msg("The withdrawal epoch duration can't be negative.")]
+    InvalidWithdrawEpochDuration,
+    #[msg("The LP tokens are locked.")]
+    LpTokensLocked,
+    #[msg("The lockup duration can't be negative.")]
+    InvalidLockupDuration,
+    #[msg("The owner is not the one of the market.")]
+    InvalidMarketOwner,
+    #[msg("The liquidity pool token account is not the one of the market.")]
+    InvalidLiquidityPoolTokenAccount,
+    #[msg("The delegate is not authorized to trade for the LP holder.")]
+    UnauthorizedDelegate,
+    #[msg("The withdrawal request is not pending in the current epoch.")]
+    WithdrawRequestNotPending,
+    #[msg("The withdrawal request has no LP tokens left.")]
+    WithdrawRequestEmpty,
 }
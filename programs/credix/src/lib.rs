@@ -4,6 +4,7 @@ mod processor;
 mod spl_token_utils;
 pub mod state;
 mod util;
+pub mod withdrawals;
 
 use crate::context::*;
 use anchor_lang::prelude::*;
@@ -12,6 +13,8 @@ use state::*;
 declare_id!("6i5vHpj1fVDqaWxknwH8mfCjkm2zZVwgDtfPMg19nzQK");
 
 pub const CREDIX_PASS_SEED: &str = "credix-pass";
+pub const WITHDRAW_REQUEST_SEED: &str = "withdraw-request";
+pub const WITHDRAW_ESCROW_SEED: &str = "withdraw-escrow";
 
 pub mod gateway_program {
     use anchor_lang::prelude::declare_id;
@@ -55,6 +58,100 @@ pub mod credix {
         processor::process_update_credix_pass(ctx, is_active)
     }
 
+    pub fn close_credix_pass(_ctx: Context<CloseCredixPass>) -> ProgramResult {
+        msg!("Close Credix pass");
+        Ok(())
+    }
+
+    pub fn update_price_band(
+        ctx: Context<UpdatePriceBand>,
+        price_band_bps: u16,
+        trading_halted: bool,
+    ) -> ProgramResult {
+        msg!(
+            "Update price band with price_band_bps: {}, trading_halted: {}",
+            price_band_bps,
+            trading_halted
+        );
+        processor::process_update_price_band(ctx, price_band_bps, trading_halted)
+    }
+
+    pub fn update_deposit_cap(
+        ctx: Context<UpdateDepositCap>,
+        max_total_deposits: u64,
+    ) -> ProgramResult {
+        msg!(
+            "Update deposit cap with max_total_deposits: {}",
+            max_total_deposits
+        );
+        processor::process_update_deposit_cap(ctx, max_total_deposits)
+    }
+
+    pub fn update_investor_deposit_cap(
+        ctx: Context<UpdateInvestorDepositCap>,
+        max_deposits: u64,
+    ) -> ProgramResult {
+        msg!(
+            "Update investor deposit cap with max_deposits: {}",
+            max_deposits
+        );
+        processor::process_update_investor_deposit_cap(ctx, max_deposits)
+    }
+
+    pub fn update_lp_lockup(ctx: Context<UpdateLpLockup>, lockup_duration: i64) -> ProgramResult {
+        msg!("Update LP lockup with lockup_duration: {}", lockup_duration);
+        processor::process_update_lp_lockup(ctx, lockup_duration)
+    }
+
+    pub fn update_withdraw_epochs(
+        ctx: Context<UpdateWithdrawEpochs>,
+        epoch_duration: i64,
+    ) -> ProgramResult {
+        msg!(
+            "Update withdraw epochs with epoch_duration: {}",
+            epoch_duration
+        );
+        processor::process_update_withdraw_epochs(ctx, epoch_duration)
+    }
+
+    pub fn create_withdraw_request(
+        ctx: Context<CreateWithdrawRequest>,
+        amount: u64,
+    ) -> ProgramResult {
+        msg!("Create withdraw request with amount {}", amount);
+        processor::process_create_withdraw_request(ctx, amount)
+    }
+
+    pub fn close_withdraw_epoch(ctx: Context<CloseWithdrawEpoch>) -> ProgramResult {
+        msg!("Close withdraw epoch");
+        processor::process_close_withdraw_epoch(ctx)
+    }
+
+    pub fn fill_withdraw_request(ctx: Context<FillWithdrawRequest>) -> ProgramResult {
+        msg!("Fill withdraw request");
+        processor::process_fill_withdraw_request(ctx)
+    }
+
+    pub fn cancel_withdraw_request(ctx: Context<CancelWithdrawRequest>) -> ProgramResult {
+        msg!("Cancel withdraw request");
+        processor::process_cancel_withdraw_request(ctx)
+    }
+
+    pub fn refund_withdraw_request(ctx: Context<RefundWithdrawRequest>) -> ProgramResult {
+        msg!("Refund withdraw request");
+        processor::process_refund_withdraw_request(ctx)
+    }
+
+    pub fn migrate_global_market_state(ctx: Context<MigrateGlobalMarketState>) -> ProgramResult {
+        msg!("Migrate global market state");
+        processor::process_migrate_global_market_state(ctx)
+    }
+
+    pub fn migrate_credix_pass(ctx: Context<MigrateCredixPass>) -> ProgramResult {
+        msg!("Migrate Credix pass");
+        processor::process_migrate_credix_pass(ctx)
+    }
+
     pub fn freeze_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
         processor::freeze_lp_tokens(ctx)
     }
@@ -62,4 +159,56 @@ pub mod credix {
     pub fn thaw_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
         processor::thaw_lp_tokens(ctx)
     }
+
+    /// Thaws LP tokens about to leave the wallet of the LP holder, once their
+    /// lockup is over.
+    pub fn thaw_unlocked_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
+        processor::thaw_unlocked_lp_tokens(ctx)
+    }
+
+    /// Freezes the LP tokens of a holder after a trading delegate of theirs
+    /// traded on the permissioned market.
+    /// The permission is the one of the proxied instruction the delegate
+    /// signs, `PLACE_ORDERS` or `CANCEL_ORDERS` of the permissioned market.
+    pub fn freeze_delegated_lp_tokens(
+        ctx: Context<FreezeThawDelegatedLpTokens>,
+        permission: u8,
+    ) -> ProgramResult {
+        processor::freeze_delegated_lp_tokens(ctx, permission)
+    }
+
+    pub fn thaw_delegated_lp_tokens(
+        ctx: Context<FreezeThawDelegatedLpTokens>,
+        permission: u8,
+    ) -> ProgramResult {
+        processor::thaw_delegated_lp_tokens(ctx, permission)
+    }
+
+    pub fn thaw_unlocked_delegated_lp_tokens(
+        ctx: Context<FreezeThawDelegatedLpTokens>,
+        permission: u8,
+    ) -> ProgramResult {
+        processor::thaw_unlocked_delegated_lp_tokens(ctx, permission)
+    }
+
+    /// Lets the LP holder approve a delegate, e.g. their open orders account
+    /// for asks placed by a trading delegate, on their frozen LP tokens.
+    pub fn approve_lp_tokens(ctx: Context<ApproveLpTokens>, amount: u64) -> ProgramResult {
+        msg!("Approve LP tokens");
+        processor::process_approve_lp_tokens(ctx, amount)
+    }
+}
+
+/// Dispatches an instruction like the program entrypoint does. Dependents
+/// build the program with `no-entrypoint`, this lets them run it natively,
+/// e.g. to simulate requests off-chain.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.len() < 8 {
+        return Err(anchor_lang::__private::ErrorCode::InstructionMissing.into());
+    }
+    dispatch(program_id, accounts, data)
 }
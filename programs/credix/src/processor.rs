@@ -1,5 +1,12 @@
-use crate::{util::civic_check, *};
+use crate::{
+    errors::ErrorCode,
+    util::{civic_check, grow_account},
+    withdrawals::EpochFill,
+    *,
+};
 use anchor_lang::prelude::*;
+use anchor_spl::associated_token;
+use serum_dex_permissioned::TradingDelegate;
 use spl_token_utils::*;
 
 pub fn process_initialize_market(
@@ -13,6 +20,8 @@ pub fn process_initialize_market(
     ctx.accounts.global_market_state.signing_authority_bump = signing_authority_bump;
     ctx.accounts.global_market_state.bump = global_market_state_bump;
     ctx.accounts.global_market_state.gatekeeper_network = ctx.accounts.gatekeeper_network.key();
+    ctx.accounts.global_market_state.owner = ctx.accounts.owner.key();
+    ctx.accounts.global_market_state.version = ACCOUNT_VERSION;
 
     ctx.accounts
         .global_market_state
@@ -22,6 +31,21 @@ pub fn process_initialize_market(
 }
 
 pub fn process_deposit(ctx: Context<DepositFunds>, amount: u64) -> ProgramResult {
+    add_deposit(
+        &mut ctx.accounts.global_market_state,
+        &mut ctx.accounts.credix_pass,
+        amount,
+    )?;
+
+    // The lockup restarts with every deposit, for all LP tokens of the holder.
+    let lp_lockup_duration = ctx.accounts.global_market_state.lp_lockup_duration;
+    if lp_lockup_duration > 0 {
+        ctx.accounts.credix_pass.locked_until = Clock::get()?
+            .unix_timestamp
+            .checked_add(lp_lockup_duration)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
     transfer_base(
         amount,
         &ctx.accounts.investor_token_account.to_account_info(),
@@ -39,12 +63,52 @@ pub fn process_deposit(ctx: Context<DepositFunds>, amount: u64) -> ProgramResult
         amount,
     )?;
 
+    ctx.accounts.global_market_state.pool_value = ctx
+        .accounts
+        .global_market_state
+        .pool_value
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+// Adds a deposit to the totals of the pool and of the investor, rejecting it
+// beyond either cap.
+fn add_deposit(
+    global_market_state: &mut GlobalMarketState,
+    credix_pass: &mut CredixPass,
+    amount: u64,
+) -> ProgramResult {
+    let total_deposits = global_market_state
+        .total_deposits
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(
+        global_market_state.max_total_deposits == 0
+            || total_deposits <= global_market_state.max_total_deposits,
+        ErrorCode::PoolDepositCapExceeded
+    );
+
+    let investor_deposits = credix_pass
+        .total_deposits
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(
+        credix_pass.max_deposits == 0 || investor_deposits <= credix_pass.max_deposits,
+        ErrorCode::InvestorDepositCapExceeded
+    );
+
+    global_market_state.total_deposits = total_deposits;
+    credix_pass.total_deposits = investor_deposits;
+
     Ok(())
 }
 
 pub fn process_create_credix_pass(ctx: Context<CreateCredixPass>, pass_bump: u8) -> ProgramResult {
     ctx.accounts.credix_pass.active = true;
     ctx.accounts.credix_pass.bump = pass_bump;
+    ctx.accounts.credix_pass.version = ACCOUNT_VERSION;
 
     Ok(())
 }
@@ -58,6 +122,364 @@ pub fn process_update_credix_pass(
     Ok(())
 }
 
+pub fn process_update_price_band(
+    ctx: Context<UpdatePriceBand>,
+    price_band_bps: u16,
+    trading_halted: bool,
+) -> ProgramResult {
+    ctx.accounts.global_market_state.price_band_bps = price_band_bps;
+    ctx.accounts.global_market_state.trading_halted = trading_halted;
+
+    Ok(())
+}
+
+pub fn process_update_deposit_cap(
+    ctx: Context<UpdateDepositCap>,
+    max_total_deposits: u64,
+) -> ProgramResult {
+    ctx.accounts.global_market_state.max_total_deposits = max_total_deposits;
+
+    Ok(())
+}
+
+pub fn process_update_investor_deposit_cap(
+    ctx: Context<UpdateInvestorDepositCap>,
+    max_deposits: u64,
+) -> ProgramResult {
+    ctx.accounts.credix_pass.max_deposits = max_deposits;
+
+    Ok(())
+}
+
+pub fn process_update_lp_lockup(
+    ctx: Context<UpdateLpLockup>,
+    lockup_duration: i64,
+) -> ProgramResult {
+    require!(lockup_duration >= 0, ErrorCode::InvalidLockupDuration);
+    ctx.accounts.global_market_state.lp_lockup_duration = lockup_duration;
+
+    Ok(())
+}
+
+pub fn process_update_withdraw_epochs(
+    ctx: Context<UpdateWithdrawEpochs>,
+    epoch_duration: i64,
+) -> ProgramResult {
+    require!(epoch_duration >= 0, ErrorCode::InvalidWithdrawEpochDuration);
+    let global_market_state = &mut ctx.accounts.global_market_state;
+    // The first epoch starts when epochs are configured.
+    if global_market_state.withdraw_epoch_start == 0 {
+        global_market_state.withdraw_epoch_start = Clock::get()?.unix_timestamp;
+    }
+    global_market_state.withdraw_epoch_duration = epoch_duration;
+
+    Ok(())
+}
+
+pub fn process_create_withdraw_request(
+    ctx: Context<CreateWithdrawRequest>,
+    amount: u64,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.global_market_state.withdraw_epoch_duration > 0,
+        ErrorCode::WithdrawEpochsDisabled
+    );
+    require!(
+        ctx.accounts.withdraw_request.lp_amount == 0,
+        ErrorCode::WithdrawRequestPending
+    );
+    require!(amount > 0, ErrorCode::NotEnoughLPTokens);
+    require_unlocked(&ctx.accounts.credix_pass)?;
+
+    lock_lp_tokens(
+        &ctx.accounts.token_program,
+        &mut ctx.accounts.investor_lp_token_account,
+        &mut ctx.accounts.escrow,
+        &ctx.accounts.investor,
+        &ctx.accounts.lp_token_mint_account,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.global_market_state,
+        amount,
+    )?;
+
+    let global_market_state = &mut ctx.accounts.global_market_state;
+    let withdraw_request = &mut ctx.accounts.withdraw_request;
+    withdraw_request.investor = ctx.accounts.investor.key();
+    withdraw_request.epoch = global_market_state.withdraw_epoch;
+    withdraw_request.lp_amount = amount;
+    withdraw_request.version = ACCOUNT_VERSION;
+
+    add_pending_withdrawal(global_market_state, amount)
+}
+
+pub fn process_close_withdraw_epoch(ctx: Context<CloseWithdrawEpoch>) -> ProgramResult {
+    let now = Clock::get()?.unix_timestamp;
+    let liquidity = ctx.accounts.liquidity_pool_token_account.amount;
+    let lp_supply = ctx.accounts.lp_token_mint_account.supply;
+    let global_market_state = &mut ctx.accounts.global_market_state;
+
+    // Without epochs, the requests left are closed at any time.
+    let epoch_duration = global_market_state.withdraw_epoch_duration;
+    let epoch_end = global_market_state
+        .withdraw_epoch_start
+        .checked_add(epoch_duration)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(now >= epoch_end, ErrorCode::WithdrawEpochNotOver);
+    require!(
+        global_market_state.withdraw_unfilled_requests == 0,
+        ErrorCode::WithdrawRequestsNotFilled
+    );
+
+    let fill = EpochFill::new(
+        global_market_state.withdraw_pending_lp,
+        global_market_state.pool_value,
+        lp_supply,
+        liquidity,
+    )?;
+    global_market_state.withdraw_closed_lp = fill.closed_lp;
+    global_market_state.withdraw_fill_lp = fill.fill_lp;
+    global_market_state.withdraw_fill_base = fill.fill_base;
+    global_market_state.withdraw_unfilled_requests = global_market_state.withdraw_pending_requests;
+    global_market_state.withdraw_pending_lp = 0;
+    global_market_state.withdraw_pending_requests = 0;
+    global_market_state.withdraw_epoch = global_market_state
+        .withdraw_epoch
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    // The next epoch starts at the last epoch boundary passed.
+    global_market_state.withdraw_epoch_start = match epoch_duration {
+        0 => now,
+        _ => {
+            let elapsed = now - global_market_state.withdraw_epoch_start;
+            global_market_state.withdraw_epoch_start + elapsed - elapsed % epoch_duration
+        }
+    };
+
+    Ok(())
+}
+
+pub fn process_fill_withdraw_request(ctx: Context<FillWithdrawRequest>) -> ProgramResult {
+    let global_market_state = &ctx.accounts.global_market_state;
+    let withdraw_request = &ctx.accounts.withdraw_request;
+    require!(
+        withdraw_request.lp_amount > 0
+            && withdraw_request.epoch.checked_add(1) == Some(global_market_state.withdraw_epoch)
+            && global_market_state.withdraw_unfilled_requests > 0,
+        ErrorCode::WithdrawRequestNotDue
+    );
+
+    let epoch_fill = EpochFill {
+        closed_lp: global_market_state.withdraw_closed_lp,
+        fill_lp: global_market_state.withdraw_fill_lp,
+        fill_base: global_market_state.withdraw_fill_base,
+    };
+    let fill = epoch_fill.request_fill(withdraw_request.lp_amount)?;
+
+    burn_lp_tokens(
+        &ctx.accounts.token_program,
+        &mut ctx.accounts.escrow,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.lp_token_mint_account,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.global_market_state,
+        fill.lp_amount,
+    )?;
+
+    let seeds = &[
+        ctx.accounts
+            .global_market_state
+            .to_account_info()
+            .key
+            .as_ref(),
+        &[ctx.accounts.global_market_state.signing_authority_bump],
+    ];
+    transfer_base_with_signer(
+        fill.base_amount,
+        &ctx.accounts.liquidity_pool_token_account.to_account_info(),
+        &ctx.accounts.investor_token_account.to_account_info(),
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.token_program,
+        &[&seeds[..]],
+    )?;
+
+    let global_market_state = &mut ctx.accounts.global_market_state;
+    global_market_state.pool_value = global_market_state
+        .pool_value
+        .checked_sub(fill.base_amount)
+        .ok_or(ErrorCode::Underflow)?;
+    // Withdrawn gains leave no deposits rather than negative ones.
+    global_market_state.total_deposits = global_market_state
+        .total_deposits
+        .saturating_sub(fill.base_amount);
+    let credix_pass = &mut ctx.accounts.credix_pass;
+    credix_pass.total_deposits = credix_pass.total_deposits.saturating_sub(fill.base_amount);
+    global_market_state.withdraw_unfilled_requests -= 1;
+
+    // What isn't filled is pending in the current epoch.
+    let withdraw_request = &mut ctx.accounts.withdraw_request;
+    withdraw_request.lp_amount = fill.remaining_lp;
+    if fill.remaining_lp > 0 {
+        withdraw_request.epoch = global_market_state.withdraw_epoch;
+        add_pending_withdrawal(global_market_state, fill.remaining_lp)?;
+    }
+
+    Ok(())
+}
+
+pub fn process_cancel_withdraw_request(ctx: Context<CancelWithdrawRequest>) -> ProgramResult {
+    let lp_amount = ctx.accounts.withdraw_request.lp_amount;
+    require!(
+        lp_amount > 0
+            && ctx.accounts.withdraw_request.epoch
+                == ctx.accounts.global_market_state.withdraw_epoch,
+        ErrorCode::WithdrawRequestNotPending
+    );
+
+    unlock_lp_tokens(
+        &ctx.accounts.token_program,
+        &mut ctx.accounts.escrow,
+        &mut ctx.accounts.investor_lp_token_account,
+        &ctx.accounts.lp_token_mint_account,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.global_market_state,
+        lp_amount,
+    )?;
+
+    remove_withdraw_request(
+        &mut ctx.accounts.global_market_state,
+        &mut ctx.accounts.withdraw_request,
+    )
+}
+
+pub fn process_refund_withdraw_request(ctx: Context<RefundWithdrawRequest>) -> ProgramResult {
+    let lp_amount = ctx.accounts.withdraw_request.lp_amount;
+    require!(lp_amount > 0, ErrorCode::WithdrawRequestEmpty);
+
+    unlock_lp_tokens(
+        &ctx.accounts.token_program,
+        &mut ctx.accounts.escrow,
+        &mut ctx.accounts.investor_lp_token_account,
+        &ctx.accounts.lp_token_mint_account,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.global_market_state,
+        lp_amount,
+    )?;
+
+    remove_withdraw_request(
+        &mut ctx.accounts.global_market_state,
+        &mut ctx.accounts.withdraw_request,
+    )
+}
+
+fn add_pending_withdrawal(
+    global_market_state: &mut GlobalMarketState,
+    lp_amount: u64,
+) -> ProgramResult {
+    global_market_state.withdraw_pending_lp = global_market_state
+        .withdraw_pending_lp
+        .checked_add(lp_amount)
+        .ok_or(ErrorCode::Overflow)?;
+    global_market_state.withdraw_pending_requests = global_market_state
+        .withdraw_pending_requests
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+// Takes a request whose LP tokens were returned off the pending requests, or
+// off the unfilled requests of the closed epoch. Its share of the fill of that
+// epoch stays in the pool.
+fn remove_withdraw_request(
+    global_market_state: &mut GlobalMarketState,
+    withdraw_request: &mut WithdrawRequest,
+) -> ProgramResult {
+    if withdraw_request.epoch == global_market_state.withdraw_epoch {
+        global_market_state.withdraw_pending_lp = global_market_state
+            .withdraw_pending_lp
+            .checked_sub(withdraw_request.lp_amount)
+            .ok_or(ErrorCode::Underflow)?;
+        global_market_state.withdraw_pending_requests = global_market_state
+            .withdraw_pending_requests
+            .checked_sub(1)
+            .ok_or(ErrorCode::Underflow)?;
+    } else {
+        global_market_state.withdraw_unfilled_requests = global_market_state
+            .withdraw_unfilled_requests
+            .checked_sub(1)
+            .ok_or(ErrorCode::Underflow)?;
+    }
+    withdraw_request.lp_amount = 0;
+
+    Ok(())
+}
+
+pub fn process_migrate_global_market_state(
+    ctx: Context<MigrateGlobalMarketState>,
+) -> ProgramResult {
+    grow_account(
+        &ctx.accounts.global_market_state,
+        &ctx.accounts.upgrade_authority,
+        &ctx.accounts.system_program,
+        GlobalMarketState::LEN,
+    )?;
+
+    let mut global_market_state: Account<GlobalMarketState> =
+        Account::try_from(&ctx.accounts.global_market_state)?;
+    require!(
+        global_market_state.version <= ACCOUNT_VERSION,
+        ErrorCode::UnsupportedAccountVersion
+    );
+    // Version 1 added the owner, the value of the pool, the price band, the
+    // version and the reserved bytes, zeroed when the account grew.
+    if global_market_state.version == 0 {
+        let liquidity_pool_token_account = &ctx.accounts.liquidity_pool_token_account;
+        require!(
+            liquidity_pool_token_account.key()
+                == associated_token::get_associated_token_address(
+                    ctx.accounts.signing_authority.key,
+                    &global_market_state.liquidity_pool_token_mint_account,
+                ),
+            ErrorCode::InvalidLiquidityPoolTokenAccount
+        );
+        global_market_state.owner = ctx.accounts.owner.key();
+        global_market_state.pool_value = liquidity_pool_token_account.amount;
+    }
+    // Version 2 took the deposit totals and caps from the reserved bytes.
+    // Deposits minted LP tokens one to one and couldn't be withdrawn, they
+    // are the value of the pool.
+    if global_market_state.version < 2 {
+        global_market_state.total_deposits = global_market_state.pool_value;
+    }
+    require!(
+        global_market_state.owner == ctx.accounts.owner.key(),
+        ErrorCode::InvalidMarketOwner
+    );
+    global_market_state.version = ACCOUNT_VERSION;
+
+    global_market_state.exit(ctx.program_id)
+}
+
+pub fn process_migrate_credix_pass(ctx: Context<MigrateCredixPass>) -> ProgramResult {
+    grow_account(
+        &ctx.accounts.credix_pass,
+        &ctx.accounts.owner,
+        &ctx.accounts.system_program,
+        CredixPass::LEN,
+    )?;
+
+    let mut credix_pass: Account<CredixPass> = Account::try_from(&ctx.accounts.credix_pass)?;
+    require!(
+        credix_pass.version <= ACCOUNT_VERSION,
+        ErrorCode::UnsupportedAccountVersion
+    );
+    // Deposits of the holder weren't tracked before version 2, its cap only
+    // counts the ones after the migration.
+    credix_pass.version = ACCOUNT_VERSION;
+
+    credix_pass.exit(ctx.program_id)
+}
+
 pub fn freeze_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
     civic_check(
         &ctx.accounts.lp_holder,
@@ -73,6 +495,20 @@ pub fn freeze_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
     )
 }
 
+pub fn thaw_unlocked_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
+    require_unlocked(&ctx.accounts.credix_pass)?;
+    thaw_lp_tokens(ctx)
+}
+
+fn require_unlocked(credix_pass: &CredixPass) -> ProgramResult {
+    require!(
+        Clock::get()?.unix_timestamp >= credix_pass.locked_until,
+        ErrorCode::LpTokensLocked
+    );
+
+    Ok(())
+}
+
 pub fn thaw_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
     civic_check(
         &ctx.accounts.lp_holder,
@@ -87,3 +523,77 @@ pub fn thaw_lp_tokens(ctx: Context<FreezeThawLpTokens>) -> ProgramResult {
         &ctx.accounts.token_program,
     )
 }
+
+/// Checks the trading delegate record of the LP holder, registered on the
+/// permissioned market, against the delegate signing for them. A delegate
+/// never stands in for the holder without a permission.
+fn authorize_delegate(accounts: &FreezeThawDelegatedLpTokens, permission: u8) -> ProgramResult {
+    let trading_delegate =
+        TradingDelegate::load(&accounts.trading_delegate, &permissioned_market_program::ID)?;
+    require!(
+        permission != 0
+            && &trading_delegate.owner == accounts.lp_holder.key
+            && trading_delegate.authorizes(
+                accounts.delegate.key,
+                permission,
+                Clock::get()?.unix_timestamp
+            ),
+        ErrorCode::UnauthorizedDelegate
+    );
+    civic_check(
+        &accounts.lp_holder,
+        &accounts.gateway_token,
+        &accounts.global_market_state.gatekeeper_network,
+    )?;
+
+    Ok(())
+}
+
+pub fn freeze_delegated_lp_tokens(
+    ctx: Context<FreezeThawDelegatedLpTokens>,
+    permission: u8,
+) -> ProgramResult {
+    authorize_delegate(ctx.accounts, permission)?;
+    freeze_lp_token_account(
+        &mut ctx.accounts.lp_token_account,
+        &ctx.accounts.lp_token_mint_account,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.global_market_state,
+        &ctx.accounts.token_program,
+    )
+}
+
+pub fn thaw_unlocked_delegated_lp_tokens(
+    ctx: Context<FreezeThawDelegatedLpTokens>,
+    permission: u8,
+) -> ProgramResult {
+    require_unlocked(&ctx.accounts.credix_pass)?;
+    thaw_delegated_lp_tokens(ctx, permission)
+}
+
+pub fn thaw_delegated_lp_tokens(
+    ctx: Context<FreezeThawDelegatedLpTokens>,
+    permission: u8,
+) -> ProgramResult {
+    authorize_delegate(ctx.accounts, permission)?;
+    thaw_lp_token_account(
+        &mut ctx.accounts.lp_token_account,
+        &ctx.accounts.lp_token_mint_account,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.global_market_state,
+        &ctx.accounts.token_program,
+    )
+}
+
+pub fn process_approve_lp_tokens(ctx: Context<ApproveLpTokens>, amount: u64) -> ProgramResult {
+    spl_token_utils::approve_lp_tokens(
+        &ctx.accounts.token_program,
+        &mut ctx.accounts.lp_token_account,
+        &ctx.accounts.lp_holder,
+        &ctx.accounts.delegate,
+        &ctx.accounts.lp_token_mint_account,
+        &ctx.accounts.signing_authority,
+        &ctx.accounts.global_market_state,
+        amount,
+    )
+}
@@ -1,8 +1,8 @@
 use crate::*;
 use anchor_lang::prelude::*;
 use anchor_spl::token::{
-    burn, freeze_account, mint_to, thaw_account, transfer, Burn, FreezeAccount, Mint, MintTo,
-    ThawAccount, TokenAccount, Transfer,
+    approve, burn, freeze_account, mint_to, thaw_account, transfer, Approve, Burn, FreezeAccount,
+    Mint, MintTo, ThawAccount, TokenAccount, Transfer,
 };
 
 pub fn mint_lp_tokens<'a>(
@@ -50,7 +50,6 @@ pub fn mint_lp_tokens<'a>(
     )
 }
 
-#[allow(dead_code)]
 pub fn burn_lp_tokens<'a>(
     token_program_account_info: &AccountInfo<'a>,
     token_account: &mut Account<'a, TokenAccount>,
@@ -74,7 +73,18 @@ pub fn burn_lp_tokens<'a>(
         authority: owner_account.clone(),
     };
 
-    let cpi_context = CpiContext::new(token_program_account_info.to_account_info(), cpi_accounts);
+    // The signing authority owns the LP tokens in withdrawal escrows.
+    let seeds = &[
+        global_market_state_account.to_account_info().key.as_ref(),
+        &[global_market_state_account.signing_authority_bump],
+    ];
+    let signer_seeds = &[&seeds[..]];
+
+    let cpi_context = CpiContext::new_with_signer(
+        token_program_account_info.to_account_info(),
+        cpi_accounts,
+        signer_seeds,
+    );
     burn(cpi_context, amount)?;
 
     freeze_lp_token_account(
@@ -86,6 +96,143 @@ pub fn burn_lp_tokens<'a>(
     )
 }
 
+/// Moves LP tokens of an investor to an escrow, thawing the investor's
+/// account for the transfer.
+#[allow(clippy::too_many_arguments)]
+pub fn lock_lp_tokens<'a>(
+    token_program_account_info: &AccountInfo<'a>,
+    from: &mut Account<'a, TokenAccount>,
+    escrow: &mut Account<'a, TokenAccount>,
+    owner_account: &AccountInfo<'a>,
+    mint_account: &Account<'a, Mint>,
+    authority_account_info: &AccountInfo<'a>,
+    global_market_state_account: &Account<'a, GlobalMarketState>,
+    amount: u64,
+) -> ProgramResult {
+    thaw_lp_token_account(
+        from,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )?;
+    // A filled escrow is left frozen by the burn.
+    thaw_lp_token_account(
+        escrow,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )?;
+
+    let cpi_accounts = Transfer {
+        from: from.to_account_info(),
+        to: escrow.to_account_info(),
+        authority: owner_account.clone(),
+    };
+    let cpi_context = CpiContext::new(token_program_account_info.clone(), cpi_accounts);
+    transfer(cpi_context, amount)?;
+    from.reload()?;
+
+    freeze_lp_token_account(
+        from,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )
+}
+
+/// Returns LP tokens of an escrow to the investor, refreezing the investor's
+/// account after the transfer.
+pub fn unlock_lp_tokens<'a>(
+    token_program_account_info: &AccountInfo<'a>,
+    escrow: &mut Account<'a, TokenAccount>,
+    to: &mut Account<'a, TokenAccount>,
+    mint_account: &Account<'a, Mint>,
+    authority_account_info: &AccountInfo<'a>,
+    global_market_state_account: &Account<'a, GlobalMarketState>,
+    amount: u64,
+) -> ProgramResult {
+    // A partly filled escrow is left frozen by the burn.
+    thaw_lp_token_account(
+        escrow,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )?;
+    thaw_lp_token_account(
+        to,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )?;
+
+    let seeds = &[
+        global_market_state_account.to_account_info().key.as_ref(),
+        &[global_market_state_account.signing_authority_bump],
+    ];
+    transfer_base_with_signer(
+        amount,
+        &escrow.to_account_info(),
+        &to.to_account_info(),
+        authority_account_info,
+        token_program_account_info,
+        &[&seeds[..]],
+    )?;
+    escrow.reload()?;
+    to.reload()?;
+
+    freeze_lp_token_account(
+        to,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )
+}
+
+/// Approves `delegate` to spend `amount` LP tokens of the frozen account of
+/// their owner, which signs for the approval.
+#[allow(clippy::too_many_arguments)]
+pub fn approve_lp_tokens<'a>(
+    token_program_account_info: &AccountInfo<'a>,
+    token_account: &mut Account<'a, TokenAccount>,
+    owner_account: &AccountInfo<'a>,
+    delegate_account: &AccountInfo<'a>,
+    mint_account: &Account<'a, Mint>,
+    authority_account_info: &AccountInfo<'a>,
+    global_market_state_account: &Account<'a, GlobalMarketState>,
+    amount: u64,
+) -> ProgramResult {
+    thaw_lp_token_account(
+        token_account,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )?;
+
+    let cpi_accounts = Approve {
+        to: token_account.to_account_info(),
+        delegate: delegate_account.clone(),
+        authority: owner_account.clone(),
+    };
+    let cpi_context = CpiContext::new(token_program_account_info.clone(), cpi_accounts);
+    approve(cpi_context, amount)?;
+    token_account.reload()?;
+
+    freeze_lp_token_account(
+        token_account,
+        mint_account,
+        authority_account_info,
+        global_market_state_account,
+        token_program_account_info,
+    )
+}
+
 pub fn transfer_base<'a>(
     amount: u64,
     from: &AccountInfo<'a>,
@@ -104,7 +251,6 @@ pub fn transfer_base<'a>(
     transfer(cpi_context, amount)
 }
 
-#[allow(dead_code)]
 pub fn transfer_base_with_signer<'a>(
     amount: u64,
     from: &AccountInfo<'a>,
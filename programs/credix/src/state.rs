@@ -1,18 +1,141 @@
 use anchor_lang::prelude::*;
 
+/// Layout version of the accounts of the program. Accounts allocated before
+/// versioning were `2 * size_of::<T>() + 8` bytes with zeroes after their
+/// fields, where `version` now is: they read as version 0 once grown to the
+/// current length by their migration instruction. Version 2 took the
+/// deposit totals and caps from the reserved space of version 1.
+///
+/// The reserved space is kept in words, borsh only encodes byte arrays of
+/// some lengths.
+pub const ACCOUNT_VERSION: u8 = 2;
+
 #[account]
-#[derive(Default)]
 pub struct GlobalMarketState {
     pub gatekeeper_network: Pubkey,
     pub liquidity_pool_token_mint_account: Pubkey,
     pub lp_token_mint_account: Pubkey,
     pub signing_authority_bump: u8,
     pub bump: u8,
+    pub owner: Pubkey,
+    // Base tokens backing the outstanding LP tokens.
+    pub pool_value: u64,
+    // Allowed deviation of LP token limit prices from NAV on the permissioned market.
+    pub price_band_bps: u16,
+    pub trading_halted: bool,
+    pub version: u8,
+    // Base tokens deposited net of withdrawals, the fund size capped by
+    // `max_total_deposits`.
+    pub total_deposits: u64,
+    // Zero disables the cap.
+    pub max_total_deposits: u64,
+    // Withdrawal requests are filled at the end of epochs of this many
+    // seconds, zero disables new requests.
+    pub withdraw_epoch_duration: i64,
+    pub withdraw_epoch_start: i64,
+    pub withdraw_epoch: u64,
+    // LP tokens and number of the requests of the current epoch.
+    pub withdraw_pending_lp: u64,
+    pub withdraw_pending_requests: u64,
+    // Fill of the last closed epoch: the LP tokens of its requests, how many
+    // are burned for how many base tokens, and the requests left to fill.
+    pub withdraw_closed_lp: u64,
+    pub withdraw_fill_lp: u64,
+    pub withdraw_fill_base: u64,
+    pub withdraw_unfilled_requests: u64,
+    // LP tokens can't be sold or withdrawn for this many seconds after a
+    // deposit.
+    pub lp_lockup_duration: i64,
+    // New fields are taken from the reserved words, keeping the length.
+    pub reserved: [u64; 4],
+}
+
+impl GlobalMarketState {
+    /// Length of the account, discriminator included.
+    pub const LEN: usize = 8 + 32 * 4 + 1 + 1 + 8 + 2 + 1 + 1 + 8 + 8 + 8 * 10 + 8 * 4;
+}
+
+impl Default for GlobalMarketState {
+    fn default() -> Self {
+        Self {
+            gatekeeper_network: Pubkey::default(),
+            liquidity_pool_token_mint_account: Pubkey::default(),
+            lp_token_mint_account: Pubkey::default(),
+            signing_authority_bump: 0,
+            bump: 0,
+            owner: Pubkey::default(),
+            pool_value: 0,
+            price_band_bps: 0,
+            trading_halted: false,
+            version: ACCOUNT_VERSION,
+            total_deposits: 0,
+            max_total_deposits: 0,
+            withdraw_epoch_duration: 0,
+            withdraw_epoch_start: 0,
+            withdraw_epoch: 0,
+            withdraw_pending_lp: 0,
+            withdraw_pending_requests: 0,
+            withdraw_closed_lp: 0,
+            withdraw_fill_lp: 0,
+            withdraw_fill_base: 0,
+            withdraw_unfilled_requests: 0,
+            lp_lockup_duration: 0,
+            reserved: [0; 4],
+        }
+    }
 }
 
 #[account]
-#[derive(Default)]
 pub struct CredixPass {
     pub bump: u8,
     pub active: bool,
+    pub version: u8,
+    // Base tokens deposited by the pass holder net of withdrawals, capped by
+    // `max_deposits`.
+    pub total_deposits: u64,
+    // Zero disables the cap.
+    pub max_deposits: u64,
+    // End of the lockup of the LP tokens of the last deposit.
+    pub locked_until: i64,
+    // New fields are taken from the reserved words, keeping the length.
+    pub reserved: [u64; 5],
+}
+
+impl CredixPass {
+    /// Length of the account, discriminator included.
+    pub const LEN: usize = 8 + 1 + 1 + 1 + 8 + 8 + 8 + 8 * 5;
+}
+
+impl Default for CredixPass {
+    fn default() -> Self {
+        Self {
+            bump: 0,
+            active: false,
+            version: ACCOUNT_VERSION,
+            total_deposits: 0,
+            max_deposits: 0,
+            locked_until: 0,
+            reserved: [0; 5],
+        }
+    }
+}
+
+/// LP tokens of an investor locked in escrow until filled by withdrawal
+/// epochs, one per investor. Filled requests are reused by the next one.
+#[account]
+#[derive(Default)]
+pub struct WithdrawRequest {
+    pub investor: Pubkey,
+    // Epoch the request is pending in.
+    pub epoch: u64,
+    // LP tokens left in escrow.
+    pub lp_amount: u64,
+    pub version: u8,
+    // New fields are taken from the reserved words, keeping the length.
+    pub reserved: [u64; 4],
+}
+
+impl WithdrawRequest {
+    /// Length of the account, discriminator included.
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 1 + 8 * 4;
 }
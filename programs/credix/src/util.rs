@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{bpf_loader_upgradeable, program::invoke, system_instruction};
 use solana_gateway::error::GatewayError;
 use solana_gateway::{Gateway, VerificationOptions};
 pub fn civic_check<'a>(
@@ -16,3 +17,32 @@ pub fn civic_check<'a>(
         }),
     )
 }
+
+/// Grows an account of the program to `len` bytes, the payer topping up its
+/// rent exemption. Longer accounts are left as is.
+pub fn grow_account<'a>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    len: usize,
+) -> ProgramResult {
+    if account.data_len() >= len {
+        return Ok(());
+    }
+
+    let top_up = Rent::get()?
+        .minimum_balance(len)
+        .saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    account.realloc(len, false)
+}
+
+/// Program data account of the program, holding its upgrade authority.
+pub fn program_data_address() -> Pubkey {
+    Pubkey::find_program_address(&[crate::ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
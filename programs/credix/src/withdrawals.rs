@@ -0,0 +1,74 @@
+use crate::errors::ErrorCode;
+use anchor_lang::prelude::*;
+use std::convert::TryFrom;
+
+/// Fill of a closed withdrawal epoch: of the `closed_lp` LP tokens of its
+/// requests, `fill_lp` are burned for `fill_base` base tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EpochFill {
+    pub closed_lp: u64,
+    pub fill_lp: u64,
+    pub fill_base: u64,
+}
+
+/// Fill of a request of a closed epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RequestFill {
+    /// LP tokens burned from the escrow.
+    pub lp_amount: u64,
+    /// Base tokens paid to the investor.
+    pub base_amount: u64,
+    /// LP tokens left in escrow, pending in the next epoch.
+    pub remaining_lp: u64,
+}
+
+impl EpochFill {
+    /// Values the `pending_lp` LP tokens of the requests at the NAV of the
+    /// pool and fills them pro rata as far as its `liquidity` allows.
+    pub fn new(
+        pending_lp: u64,
+        pool_value: u64,
+        lp_supply: u64,
+        liquidity: u64,
+    ) -> Result<Self, ProgramError> {
+        let value = match lp_supply {
+            0 => 0,
+            _ => mul_div(pending_lp, pool_value, lp_supply)?,
+        };
+        let fill_base = value.min(liquidity);
+        let fill_lp = match value {
+            // Worthless LP tokens are burned for nothing.
+            0 => pending_lp,
+            _ => mul_div(pending_lp, fill_base, value)?,
+        };
+
+        Ok(Self {
+            closed_lp: pending_lp,
+            fill_lp,
+            fill_base,
+        })
+    }
+
+    /// Fills a request of `lp_amount` LP tokens of the epoch, its share of
+    /// the fill rounded down. The requests of an epoch never burn or pay
+    /// more than the fill of the epoch.
+    pub fn request_fill(&self, lp_amount: u64) -> Result<RequestFill, ProgramError> {
+        let fill_lp = mul_div(lp_amount, self.fill_lp, self.closed_lp)?;
+        let base_amount = mul_div(lp_amount, self.fill_base, self.closed_lp)?;
+        let remaining_lp = lp_amount.checked_sub(fill_lp).ok_or(ErrorCode::Underflow)?;
+
+        Ok(RequestFill {
+            lp_amount: fill_lp,
+            base_amount,
+            remaining_lp,
+        })
+    }
+}
+
+/// `a * b / c`, rounded down.
+pub fn mul_div(a: u64, b: u64, c: u64) -> Result<u64, ProgramError> {
+    let result = (a as u128 * b as u128)
+        .checked_div(c as u128)
+        .ok_or(ErrorCode::ZeroDivision)?;
+    u64::try_from(result).map_err(|_| ErrorCode::Overflow.into())
+}
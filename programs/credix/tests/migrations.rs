@@ -0,0 +1,405 @@
+//! Accounts allocated before their layouts were versioned, and their
+//! migration to the current layout.
+//!
+//! The migration instructions realloc accounts, which native programs can't
+//! do, so they run against the BPF build of `anchor build`:
+//!
+//! ```sh
+//! anchor build
+//! cargo test-bpf --manifest-path programs/credix/Cargo.toml
+//! ```
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+use credix::state::{CredixPass, GlobalMarketState, ACCOUNT_VERSION};
+
+// Layouts of the accounts before versioning, allocated as
+// `2 * size_of::<T>() + 8` bytes.
+#[derive(AnchorSerialize)]
+struct GlobalMarketStateV0 {
+    gatekeeper_network: Pubkey,
+    liquidity_pool_token_mint_account: Pubkey,
+    lp_token_mint_account: Pubkey,
+    signing_authority_bump: u8,
+    bump: u8,
+}
+
+const GLOBAL_MARKET_STATE_V0_LEN: usize = 2 * 98 + 8;
+
+#[derive(AnchorSerialize)]
+struct CredixPassV0 {
+    bump: u8,
+    active: bool,
+}
+
+const CREDIX_PASS_V0_LEN: usize = 2 * 2 + 8;
+
+fn global_market_state_v0() -> GlobalMarketStateV0 {
+    GlobalMarketStateV0 {
+        gatekeeper_network: Pubkey::new_unique(),
+        liquidity_pool_token_mint_account: Pubkey::new_unique(),
+        lp_token_mint_account: Pubkey::new_unique(),
+        signing_authority_bump: 254,
+        bump: 253,
+    }
+}
+
+/// Encodes an account the way anchor did, zeroes after its fields.
+fn encode_v0(discriminator: [u8; 8], account: impl AnchorSerialize, len: usize) -> Vec<u8> {
+    let mut data = discriminator.to_vec();
+    account.serialize(&mut data).unwrap();
+    data.resize(len, 0);
+    data
+}
+
+#[test]
+fn v0_global_market_state_reads_as_version_0_once_grown() {
+    let v0 = global_market_state_v0();
+    let mut data = encode_v0(
+        GlobalMarketState::discriminator(),
+        &v0,
+        GLOBAL_MARKET_STATE_V0_LEN,
+    );
+    assert!(data.len() < GlobalMarketState::LEN);
+    assert!(GlobalMarketState::try_deserialize(&mut data.as_slice()).is_err());
+
+    // As after the realloc of the migration.
+    data.resize(GlobalMarketState::LEN, 0);
+    let state = GlobalMarketState::try_deserialize(&mut data.as_slice()).unwrap();
+    assert_eq!(state.version, 0);
+    assert_eq!(state.gatekeeper_network, v0.gatekeeper_network);
+    assert_eq!(
+        state.liquidity_pool_token_mint_account,
+        v0.liquidity_pool_token_mint_account
+    );
+    assert_eq!(state.lp_token_mint_account, v0.lp_token_mint_account);
+    assert_eq!(state.signing_authority_bump, v0.signing_authority_bump);
+    assert_eq!(state.bump, v0.bump);
+    assert_eq!(state.owner, Pubkey::default());
+    assert_eq!(state.pool_value, 0);
+    assert_eq!(state.price_band_bps, 0);
+    assert!(!state.trading_halted);
+    assert_eq!(state.total_deposits, 0);
+    assert_eq!(state.max_total_deposits, 0);
+}
+
+#[test]
+fn v0_credix_pass_reads_as_version_0_once_grown() {
+    let v0 = CredixPassV0 {
+        bump: 255,
+        active: true,
+    };
+    let mut data = encode_v0(CredixPass::discriminator(), &v0, CREDIX_PASS_V0_LEN);
+    assert!(CredixPass::try_deserialize(&mut data.as_slice()).is_err());
+
+    // As after the realloc of the migration.
+    data.resize(CredixPass::LEN, 0);
+    let pass = CredixPass::try_deserialize(&mut data.as_slice()).unwrap();
+    assert_eq!(pass.version, 0);
+    assert_eq!(pass.bump, v0.bump);
+    assert!(pass.active);
+}
+
+#[test]
+fn current_layouts_are_versioned() {
+    let mut data = Vec::new();
+    GlobalMarketState::default()
+        .try_serialize(&mut data)
+        .unwrap();
+    assert_eq!(data.len(), GlobalMarketState::LEN);
+    assert_eq!(data[8 + 141], ACCOUNT_VERSION);
+
+    let mut data = Vec::new();
+    CredixPass::default().try_serialize(&mut data).unwrap();
+    assert_eq!(data.len(), CredixPass::LEN);
+    assert_eq!(data[8 + 2], ACCOUNT_VERSION);
+}
+
+#[cfg(feature = "test-bpf")]
+mod bpf {
+    use super::*;
+    use anchor_lang::solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+    use anchor_lang::solana_program::program_pack::Pack;
+    use credix::errors::ErrorCode;
+    use credix_client::{find_credix_pass, find_program_data, CredixMarket};
+    use solana_program_test::{ProgramTest, ProgramTestContext};
+    use solana_sdk::account::Account;
+    use solana_sdk::instruction::{Instruction, InstructionError};
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::{Transaction, TransactionError};
+    use solana_sdk::transport::TransportError;
+    use spl_token::state::{Account as TokenAccount, AccountState};
+
+    const GLOBAL_MARKET_SEED: &str = "credix-market";
+    // Base tokens held by the liquidity pool of the v0 market.
+    const POOL_VALUE: u64 = 1_000_000;
+
+    struct TestMarket {
+        context: ProgramTestContext,
+        market: CredixMarket,
+        upgrade_authority: Keypair,
+        owner: Keypair,
+        pass_holder: Pubkey,
+    }
+
+    impl TestMarket {
+        /// Starts a validator with a v0 global market state, its liquidity
+        /// pool and a v0 credix pass.
+        async fn new() -> Self {
+            let mut program_test = ProgramTest::default();
+            program_test.add_program("credix", credix::ID, None);
+
+            let upgrade_authority = Keypair::new();
+            let owner = Keypair::new();
+            let pass_holder = Pubkey::new_unique();
+            let v0 = global_market_state_v0();
+            let market = CredixMarket::new(
+                GLOBAL_MARKET_SEED,
+                v0.gatekeeper_network,
+                v0.liquidity_pool_token_mint_account,
+                v0.lp_token_mint_account,
+            );
+            let (credix_pass, bump) =
+                find_credix_pass(&credix::ID, &market.global_market_state, &pass_holder);
+
+            add_account(
+                &mut program_test,
+                market.global_market_state,
+                credix::ID,
+                encode_v0(
+                    GlobalMarketState::discriminator(),
+                    v0,
+                    GLOBAL_MARKET_STATE_V0_LEN,
+                ),
+            );
+            add_account(
+                &mut program_test,
+                credix_pass,
+                credix::ID,
+                encode_v0(
+                    CredixPass::discriminator(),
+                    CredixPassV0 { bump, active: true },
+                    CREDIX_PASS_V0_LEN,
+                ),
+            );
+
+            // Programs deployed by `solana program deploy` record their
+            // upgrade authority in their program data account.
+            let program_data = UpgradeableLoaderState::ProgramData {
+                slot: 0,
+                upgrade_authority_address: Some(upgrade_authority.pubkey()),
+            };
+            add_account(
+                &mut program_test,
+                find_program_data(&credix::ID).0,
+                bpf_loader_upgradeable::ID,
+                bincode::serialize(&program_data).unwrap(),
+            );
+
+            let liquidity_pool = TokenAccount {
+                mint: market.base_mint,
+                owner: market.signing_authority(),
+                amount: POOL_VALUE,
+                state: AccountState::Initialized,
+                ..TokenAccount::default()
+            };
+            let mut data = vec![0; TokenAccount::LEN];
+            liquidity_pool.pack_into_slice(&mut data);
+            add_account(
+                &mut program_test,
+                market.liquidity_pool_token_account(),
+                spl_token::ID,
+                data,
+            );
+
+            for signer in [&upgrade_authority, &owner] {
+                program_test.add_account(
+                    signer.pubkey(),
+                    Account {
+                        lamports: 1_000_000_000,
+                        ..Account::default()
+                    },
+                );
+            }
+
+            Self {
+                context: program_test.start_with_context().await,
+                market,
+                upgrade_authority,
+                owner,
+                pass_holder,
+            }
+        }
+
+        async fn process(
+            &mut self,
+            ix: Instruction,
+            signer: &Keypair,
+        ) -> std::result::Result<(), TransportError> {
+            let blockhash = self
+                .context
+                .banks_client
+                .get_latest_blockhash()
+                .await
+                .unwrap();
+            let transaction = Transaction::new_signed_with_payer(
+                &[ix],
+                Some(&self.context.payer.pubkey()),
+                &[&self.context.payer, signer],
+                blockhash,
+            );
+            self.context
+                .banks_client
+                .process_transaction(transaction)
+                .await
+        }
+
+        async fn migrate_global_market_state(&mut self) -> std::result::Result<(), TransportError> {
+            let ix = self.market.migrate_global_market_state(
+                &self.upgrade_authority.pubkey(),
+                &self.owner.pubkey(),
+            );
+            let upgrade_authority =
+                Keypair::from_bytes(&self.upgrade_authority.to_bytes()).unwrap();
+            self.process(ix, &upgrade_authority).await
+        }
+
+        async fn account(&mut self, address: Pubkey) -> Account {
+            self.context
+                .banks_client
+                .get_account(address)
+                .await
+                .unwrap()
+                .unwrap()
+        }
+    }
+
+    fn add_account(program_test: &mut ProgramTest, address: Pubkey, owner: Pubkey, data: Vec<u8>) {
+        program_test.add_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    fn custom_error(err: TransportError) -> ProgramError {
+        match err {
+            TransportError::TransactionError(TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(code),
+            )) => ProgramError::Custom(code),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn migrate_global_market_state() {
+        let mut test = TestMarket::new().await;
+        test.migrate_global_market_state().await.unwrap();
+
+        let account = test.account(test.market.global_market_state).await;
+        assert_eq!(account.data.len(), GlobalMarketState::LEN);
+        assert!(Rent::default().is_exempt(account.lamports, account.data.len()));
+        let state = GlobalMarketState::try_deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(state.version, ACCOUNT_VERSION);
+        assert_eq!(state.owner, test.owner.pubkey());
+        assert_eq!(state.pool_value, POOL_VALUE);
+        assert_eq!(state.total_deposits, POOL_VALUE);
+        assert_eq!(state.price_band_bps, 0);
+        assert!(!state.trading_halted);
+
+        // Migrating again leaves the state as is.
+        test.migrate_global_market_state().await.unwrap();
+        let migrated = test.account(test.market.global_market_state).await;
+        assert_eq!(migrated.data, account.data);
+    }
+
+    #[tokio::test]
+    async fn migrate_v1_global_market_state_backfills_its_deposits() {
+        let mut test = TestMarket::new().await;
+        // A version 1 market, deposits having added up in its pool value.
+        let mut data = test.account(test.market.global_market_state).await.data;
+        data.resize(GlobalMarketState::LEN, 0);
+        let mut state = GlobalMarketState::try_deserialize(&mut data.as_slice()).unwrap();
+        state.owner = test.owner.pubkey();
+        state.pool_value = 2 * POOL_VALUE;
+        state.version = 1;
+        let mut data = Vec::new();
+        state.try_serialize(&mut data).unwrap();
+        test.context.set_account(
+            &test.market.global_market_state,
+            &Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: credix::ID,
+                executable: false,
+                rent_epoch: 0,
+            }
+            .into(),
+        );
+
+        test.migrate_global_market_state().await.unwrap();
+        let account = test.account(test.market.global_market_state).await;
+        let state = GlobalMarketState::try_deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(state.version, ACCOUNT_VERSION);
+        assert_eq!(state.pool_value, 2 * POOL_VALUE);
+        assert_eq!(state.total_deposits, 2 * POOL_VALUE);
+        assert_eq!(state.max_total_deposits, 0);
+    }
+
+    #[tokio::test]
+    async fn migrate_credix_pass() {
+        let mut test = TestMarket::new().await;
+        // Credix passes are migrated by the owner recorded on the market.
+        test.migrate_global_market_state().await.unwrap();
+
+        let owner = Keypair::from_bytes(&test.owner.to_bytes()).unwrap();
+        let ix = test
+            .market
+            .migrate_credix_pass(&owner.pubkey(), &test.pass_holder);
+        test.process(ix, &owner).await.unwrap();
+
+        let address = test.market.credix_pass(&test.pass_holder);
+        let account = test.account(address).await;
+        assert_eq!(account.data.len(), CredixPass::LEN);
+        assert!(Rent::default().is_exempt(account.lamports, account.data.len()));
+        let pass = CredixPass::try_deserialize(&mut account.data.as_slice()).unwrap();
+        assert_eq!(pass.version, ACCOUNT_VERSION);
+        assert!(pass.active);
+
+        // Migrating again leaves the pass as is.
+        let ix = test
+            .market
+            .migrate_credix_pass(&owner.pubkey(), &test.pass_holder);
+        test.process(ix, &owner).await.unwrap();
+        assert_eq!(test.account(address).await.data, account.data);
+    }
+
+    #[tokio::test]
+    async fn only_the_upgrade_authority_migrates_the_market() {
+        let mut test = TestMarket::new().await;
+        test.upgrade_authority = Keypair::new();
+
+        let err = test.migrate_global_market_state().await.unwrap_err();
+        assert_eq!(custom_error(err), ErrorCode::UnauthorizedSigner.into());
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_migrates_credix_passes() {
+        let mut test = TestMarket::new().await;
+        test.migrate_global_market_state().await.unwrap();
+
+        let intruder = Keypair::new();
+        let ix = test
+            .market
+            .migrate_credix_pass(&intruder.pubkey(), &test.pass_holder);
+
+        let err = test.process(ix, &intruder).await.unwrap_err();
+        assert_eq!(custom_error(err), ErrorCode::UnauthorizedSigner.into());
+    }
+}
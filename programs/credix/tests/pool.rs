@@ -0,0 +1,542 @@
+//! End to end tests of the deposits and withdrawals of the credix pool.
+//!
+//! Deposits create the LP token accounts of investors, which native programs
+//! can't do in a CPI, so the credix program runs as the BPF program built by
+//! `anchor build`:
+//!
+//! ```sh
+//! anchor build
+//! cargo test-bpf --manifest-path programs/credix/Cargo.toml
+//! ```
+#![cfg(feature = "test-bpf")]
+
+use anchor_lang::prelude::{AccountDeserialize, ProgramError, Pubkey, Rent};
+use anchor_spl::associated_token::get_associated_token_address;
+use credix::errors::ErrorCode;
+use credix::state::{CredixPass, GlobalMarketState, WithdrawRequest};
+use credix_client::{find_withdraw_escrow, CredixMarket};
+use solana_gateway::state::get_gatekeeper_address_with_seed;
+use solana_program_test::{ProgramTest, ProgramTestBanksClientExt, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::program_pack::Pack;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_sdk::transport::TransportError;
+use spl_token::state::Account as TokenAccount;
+
+const GLOBAL_MARKET_SEED: &str = "credix-market";
+const DECIMALS: u8 = 6;
+const DEPOSIT: u64 = 1_000_000;
+
+struct TestMarket {
+    context: ProgramTestContext,
+    market: CredixMarket,
+}
+
+impl TestMarket {
+    /// Sets up a credix market owned by the payer of the validator, also the
+    /// gatekeeper of its network.
+    async fn new() -> Self {
+        let mut program_test = ProgramTest::default();
+        program_test.add_program("credix", credix::ID, None);
+        add_artifact(
+            &mut program_test,
+            "solana_gateway_program",
+            credix::gateway_program::ID,
+        );
+
+        let base_mint = Keypair::new();
+        let lp_token_mint = Keypair::new();
+        let gatekeeper_network = Keypair::new();
+        let mut context = program_test.start_with_context().await;
+        let payer = context.payer.pubkey();
+        let market = CredixMarket::new(
+            GLOBAL_MARKET_SEED,
+            gatekeeper_network.pubkey(),
+            base_mint.pubkey(),
+            lp_token_mint.pubkey(),
+        );
+
+        let rent = context.banks_client.get_rent().await.unwrap();
+        let ixs = vec![
+            system_instruction::create_account(
+                &payer,
+                &base_mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint(
+                &spl_token::ID,
+                &base_mint.pubkey(),
+                &payer,
+                None,
+                DECIMALS,
+            )
+            .unwrap(),
+            market.initialize_market(&payer),
+            solana_gateway::instruction::add_gatekeeper(
+                &payer,
+                &payer,
+                &gatekeeper_network.pubkey(),
+            ),
+        ];
+        process(
+            &mut context,
+            &ixs,
+            &[&base_mint, &lp_token_mint, &gatekeeper_network],
+        )
+        .await
+        .unwrap();
+
+        Self { context, market }
+    }
+
+    /// Creates an investor with `base_amount` base tokens, a credix pass and
+    /// a gateway token, returning it with its base token account.
+    async fn investor(&mut self, base_amount: u64) -> (Keypair, Pubkey) {
+        let payer = self.context.payer.pubkey();
+        let investor = Keypair::new();
+        let base_account = Keypair::new();
+        let (gatekeeper_account, _) =
+            get_gatekeeper_address_with_seed(&payer, &self.market.gatekeeper_network);
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+
+        let ixs = vec![
+            system_instruction::transfer(&payer, &investor.pubkey(), 10_000_000_000),
+            system_instruction::create_account(
+                &payer,
+                &base_account.pubkey(),
+                rent.minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account(
+                &spl_token::ID,
+                &base_account.pubkey(),
+                &self.market.base_mint,
+                &investor.pubkey(),
+            )
+            .unwrap(),
+            spl_token::instruction::mint_to(
+                &spl_token::ID,
+                &self.market.base_mint,
+                &base_account.pubkey(),
+                &payer,
+                &[],
+                base_amount,
+            )
+            .unwrap(),
+            self.market.create_credix_pass(&payer, &investor.pubkey()),
+            solana_gateway::instruction::issue_vanilla(
+                &payer,
+                &investor.pubkey(),
+                &gatekeeper_account,
+                &payer,
+                &self.market.gatekeeper_network,
+                None,
+                None,
+            ),
+        ];
+        process(&mut self.context, &ixs, &[&base_account])
+            .await
+            .unwrap();
+        (investor, base_account.pubkey())
+    }
+
+    async fn deposit(
+        &mut self,
+        investor: &Keypair,
+        base_account: &Pubkey,
+        amount: u64,
+    ) -> Result<(), TransportError> {
+        let ix = self
+            .market
+            .deposit_funds(&investor.pubkey(), base_account, amount);
+        process(&mut self.context, &[ix], &[investor]).await
+    }
+
+    /// Sends an instruction signed by the owner of the market, the payer of
+    /// the validator.
+    async fn send_as_owner(&mut self, ix: Instruction) -> Result<(), TransportError> {
+        process(&mut self.context, &[ix], &[]).await
+    }
+
+    async fn account<T: AccountDeserialize>(&mut self, address: Pubkey) -> T {
+        let account = self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    async fn global_market_state(&mut self) -> GlobalMarketState {
+        let address = self.market.global_market_state;
+        self.account(address).await
+    }
+
+    async fn credix_pass(&mut self, investor: &Pubkey) -> CredixPass {
+        let address = self.market.credix_pass(investor);
+        self.account(address).await
+    }
+
+    async fn withdraw_request(&mut self, investor: &Pubkey) -> WithdrawRequest {
+        let address = self.market.withdraw_request(investor);
+        self.account(address).await
+    }
+
+    async fn token_account(&mut self, address: Pubkey) -> TokenAccount {
+        let account = self
+            .context
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        TokenAccount::unpack(&account.data).unwrap()
+    }
+
+    /// Waits for a new blockhash, for transactions repeating earlier ones.
+    async fn next_blockhash(&mut self) {
+        let banks_client = &mut self.context.banks_client;
+        let blockhash = banks_client.get_latest_blockhash().await.unwrap();
+        banks_client
+            .get_new_latest_blockhash(&blockhash)
+            .await
+            .unwrap();
+    }
+
+    async fn escrow(&mut self, investor: &Pubkey) -> TokenAccount {
+        let withdraw_request = self.market.withdraw_request(investor);
+        let address = find_withdraw_escrow(&credix::ID, &withdraw_request).0;
+        self.token_account(address).await
+    }
+
+    /// Creates an investor with `DEPOSIT` LP tokens and a withdrawal request
+    /// of `lp_amount` of them, pending in an epoch that can be closed right
+    /// away.
+    async fn withdrawing_investor(&mut self, lp_amount: u64) -> Keypair {
+        let (investor, base_account) = self.investor(DEPOSIT).await;
+        self.deposit(&investor, &base_account, DEPOSIT)
+            .await
+            .unwrap();
+        let owner = self.context.payer.pubkey();
+        let ix = self.market.update_withdraw_epochs(&owner, 1);
+        self.send_as_owner(ix).await.unwrap();
+        let ix = self
+            .market
+            .create_withdraw_request(&investor.pubkey(), lp_amount);
+        process(&mut self.context, &[ix], &[&investor])
+            .await
+            .unwrap();
+        let ix = self.market.update_withdraw_epochs(&owner, 0);
+        self.send_as_owner(ix).await.unwrap();
+        investor
+    }
+}
+
+#[tokio::test]
+async fn deposits_are_capped_for_the_pool() {
+    let mut test = TestMarket::new().await;
+    let (first, first_base_account) = test.investor(DEPOSIT).await;
+    let (second, second_base_account) = test.investor(DEPOSIT).await;
+    let owner = test.context.payer.pubkey();
+    let ix = test
+        .market
+        .update_deposit_cap(&owner, DEPOSIT + DEPOSIT / 2);
+    test.send_as_owner(ix).await.unwrap();
+
+    test.deposit(&first, &first_base_account, DEPOSIT)
+        .await
+        .unwrap();
+    let err = test
+        .deposit(&second, &second_base_account, DEPOSIT)
+        .await
+        .unwrap_err();
+    assert_eq!(custom_error(err), ErrorCode::PoolDepositCapExceeded.into());
+
+    // Up to the cap.
+    test.deposit(&second, &second_base_account, DEPOSIT / 2)
+        .await
+        .unwrap();
+    let state = test.global_market_state().await;
+    assert_eq!(state.total_deposits, DEPOSIT + DEPOSIT / 2);
+    assert_eq!(state.pool_value, DEPOSIT + DEPOSIT / 2);
+
+    // Lifting the cap.
+    let ix = test.market.update_deposit_cap(&owner, 0);
+    test.send_as_owner(ix).await.unwrap();
+    test.deposit(&second, &second_base_account, DEPOSIT / 2)
+        .await
+        .unwrap();
+    assert_eq!(test.global_market_state().await.total_deposits, 2 * DEPOSIT);
+}
+
+#[tokio::test]
+async fn deposits_are_capped_per_investor() {
+    let mut test = TestMarket::new().await;
+    let (investor, base_account) = test.investor(DEPOSIT).await;
+    let (other, other_base_account) = test.investor(DEPOSIT).await;
+    let owner = test.context.payer.pubkey();
+    let ix = test
+        .market
+        .update_investor_deposit_cap(&owner, &investor.pubkey(), DEPOSIT / 2);
+    test.send_as_owner(ix).await.unwrap();
+
+    test.deposit(&investor, &base_account, DEPOSIT / 4)
+        .await
+        .unwrap();
+    let err = test
+        .deposit(&investor, &base_account, DEPOSIT / 2)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        ErrorCode::InvestorDepositCapExceeded.into()
+    );
+    test.deposit(&investor, &base_account, DEPOSIT / 4)
+        .await
+        .unwrap();
+    let pass = test.credix_pass(&investor.pubkey()).await;
+    assert_eq!(pass.total_deposits, DEPOSIT / 2);
+    assert_eq!(pass.max_deposits, DEPOSIT / 2);
+
+    // The caps of other investors are their own.
+    test.deposit(&other, &other_base_account, DEPOSIT)
+        .await
+        .unwrap();
+    assert_eq!(
+        test.credix_pass(&other.pubkey()).await.total_deposits,
+        DEPOSIT
+    );
+}
+
+#[tokio::test]
+async fn only_the_owner_updates_deposit_caps() {
+    let mut test = TestMarket::new().await;
+    let (investor, _) = test.investor(DEPOSIT).await;
+
+    let ixs = [
+        test.market.update_deposit_cap(&investor.pubkey(), 1),
+        test.market
+            .update_investor_deposit_cap(&investor.pubkey(), &investor.pubkey(), 1),
+    ];
+    for ix in ixs {
+        let err = process(&mut test.context, &[ix], &[&investor])
+            .await
+            .unwrap_err();
+        assert_eq!(custom_error(err), ErrorCode::UnauthorizedSigner.into());
+    }
+    assert_eq!(test.global_market_state().await.max_total_deposits, 0);
+    assert_eq!(test.credix_pass(&investor.pubkey()).await.max_deposits, 0);
+}
+
+#[tokio::test]
+async fn withdrawals_are_filled_into_base_token_accounts_created_by_the_cranker() {
+    let mut test = TestMarket::new().await;
+    let investor = test.withdrawing_investor(DEPOSIT / 4).await;
+    let state = test.global_market_state().await;
+    assert_eq!(state.withdraw_pending_lp, DEPOSIT / 4);
+    assert_eq!(state.withdraw_pending_requests, 1);
+    assert_eq!(test.escrow(&investor.pubkey()).await.amount, DEPOSIT / 4);
+
+    // Without epochs, the epoch closes right away.
+    test.send_as_owner(test.market.close_withdraw_epoch())
+        .await
+        .unwrap();
+    let cranker = test.context.payer.pubkey();
+    let ix = test
+        .market
+        .fill_withdraw_request(&cranker, &investor.pubkey());
+    test.send_as_owner(ix).await.unwrap();
+
+    let base_account = get_associated_token_address(&investor.pubkey(), &test.market.base_mint);
+    assert_eq!(test.token_account(base_account).await.amount, DEPOSIT / 4);
+    assert_eq!(test.escrow(&investor.pubkey()).await.amount, 0);
+    assert_eq!(test.withdraw_request(&investor.pubkey()).await.lp_amount, 0);
+    let state = test.global_market_state().await;
+    assert_eq!(state.pool_value, DEPOSIT - DEPOSIT / 4);
+    assert_eq!(state.total_deposits, DEPOSIT - DEPOSIT / 4);
+    assert_eq!(state.withdraw_unfilled_requests, 0);
+    assert_eq!(state.withdraw_pending_requests, 0);
+}
+
+#[tokio::test]
+async fn withdrawals_free_the_deposit_cap_of_the_investor() {
+    let mut test = TestMarket::new().await;
+    let investor = test.withdrawing_investor(DEPOSIT).await;
+    let owner = test.context.payer.pubkey();
+    let ix = test
+        .market
+        .update_investor_deposit_cap(&owner, &investor.pubkey(), DEPOSIT);
+    test.send_as_owner(ix).await.unwrap();
+    assert_eq!(
+        test.credix_pass(&investor.pubkey()).await.total_deposits,
+        DEPOSIT
+    );
+
+    test.send_as_owner(test.market.close_withdraw_epoch())
+        .await
+        .unwrap();
+    let ix = test
+        .market
+        .fill_withdraw_request(&owner, &investor.pubkey());
+    test.send_as_owner(ix).await.unwrap();
+    assert_eq!(test.credix_pass(&investor.pubkey()).await.total_deposits, 0);
+
+    // Up to the cap again, from the withdrawn base tokens.
+    let base_account = get_associated_token_address(&investor.pubkey(), &test.market.base_mint);
+    test.deposit(&investor, &base_account, DEPOSIT)
+        .await
+        .unwrap();
+    assert_eq!(
+        test.credix_pass(&investor.pubkey()).await.total_deposits,
+        DEPOSIT
+    );
+}
+
+#[tokio::test]
+async fn investors_cancel_pending_withdrawal_requests() {
+    let mut test = TestMarket::new().await;
+    let investor = test.withdrawing_investor(DEPOSIT / 4).await;
+
+    let ix = test.market.cancel_withdraw_request(&investor.pubkey());
+    process(&mut test.context, &[ix], &[&investor])
+        .await
+        .unwrap();
+
+    let lp_token_account = test.market.lp_token_account(&investor.pubkey());
+    let lp_token_account = test.token_account(lp_token_account).await;
+    assert_eq!(lp_token_account.amount, DEPOSIT);
+    assert!(lp_token_account.is_frozen());
+    assert_eq!(test.escrow(&investor.pubkey()).await.amount, 0);
+    assert_eq!(test.withdraw_request(&investor.pubkey()).await.lp_amount, 0);
+    let state = test.global_market_state().await;
+    assert_eq!(state.withdraw_pending_lp, 0);
+    assert_eq!(state.withdraw_pending_requests, 0);
+}
+
+#[tokio::test]
+async fn the_owner_refunds_unfilled_withdrawal_requests() {
+    let mut test = TestMarket::new().await;
+    let investor = test.withdrawing_investor(DEPOSIT / 4).await;
+    test.send_as_owner(test.market.close_withdraw_epoch())
+        .await
+        .unwrap();
+
+    // Closed requests are the cranker's to fill.
+    let ix = test.market.cancel_withdraw_request(&investor.pubkey());
+    let err = process(&mut test.context, &[ix], &[&investor])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        custom_error(err),
+        ErrorCode::WithdrawRequestNotPending.into()
+    );
+    let ix = test
+        .market
+        .refund_withdraw_request(&investor.pubkey(), &investor.pubkey());
+    let err = process(&mut test.context, &[ix], &[&investor])
+        .await
+        .unwrap_err();
+    assert_eq!(custom_error(err), ErrorCode::UnauthorizedSigner.into());
+
+    let owner = test.context.payer.pubkey();
+    let ix = test
+        .market
+        .refund_withdraw_request(&owner, &investor.pubkey());
+    test.send_as_owner(ix).await.unwrap();
+
+    let lp_token_account = test.market.lp_token_account(&investor.pubkey());
+    assert_eq!(test.token_account(lp_token_account).await.amount, DEPOSIT);
+    assert_eq!(test.escrow(&investor.pubkey()).await.amount, 0);
+    let state = test.global_market_state().await;
+    assert_eq!(state.withdraw_unfilled_requests, 0);
+    assert_eq!(state.pool_value, DEPOSIT);
+
+    // The epoch is done with and the next one closes.
+    test.next_blockhash().await;
+    let ix = test
+        .market
+        .refund_withdraw_request(&owner, &investor.pubkey());
+    let err = test.send_as_owner(ix).await.unwrap_err();
+    assert_eq!(custom_error(err), ErrorCode::WithdrawRequestEmpty.into());
+    test.send_as_owner(test.market.close_withdraw_epoch())
+        .await
+        .unwrap();
+    assert_eq!(test.global_market_state().await.withdraw_epoch, 2);
+}
+
+#[tokio::test]
+async fn locked_lp_tokens_are_not_withdrawn() {
+    let mut test = TestMarket::new().await;
+    let (investor, base_account) = test.investor(DEPOSIT).await;
+    let owner = test.context.payer.pubkey();
+    for ix in [
+        test.market.update_lp_lockup(&owner, 3_600),
+        test.market.update_withdraw_epochs(&owner, 1),
+    ] {
+        test.send_as_owner(ix).await.unwrap();
+    }
+    test.deposit(&investor, &base_account, DEPOSIT)
+        .await
+        .unwrap();
+    assert!(test.credix_pass(&investor.pubkey()).await.locked_until > 0);
+
+    let ix = test
+        .market
+        .create_withdraw_request(&investor.pubkey(), DEPOSIT);
+    let err = process(&mut test.context, &[ix], &[&investor])
+        .await
+        .unwrap_err();
+    assert_eq!(custom_error(err), ErrorCode::LpTokensLocked.into());
+    let lp_token_account = test.market.lp_token_account(&investor.pubkey());
+    assert_eq!(test.token_account(lp_token_account).await.amount, DEPOSIT);
+    assert_eq!(test.global_market_state().await.withdraw_pending_lp, 0);
+}
+
+/// Loads a program of `artifacts`, deployed at genesis by `anchor test` too.
+fn add_artifact(program_test: &mut ProgramTest, name: &str, program_id: Pubkey) {
+    let path = format!("{}/../../artifacts/{}.so", env!("CARGO_MANIFEST_DIR"), name);
+    let data = std::fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+    program_test.add_account(
+        program_id,
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: solana_sdk::bpf_loader::id(),
+            executable: true,
+            rent_epoch: 0,
+        },
+    );
+}
+
+async fn process(
+    context: &mut ProgramTestContext,
+    ixs: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let payer = Keypair::from_bytes(&context.payer.to_bytes()).unwrap();
+    let mut all_signers = vec![&payer];
+    all_signers.extend(signers);
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction =
+        Transaction::new_signed_with_payer(ixs, Some(&payer.pubkey()), &all_signers, blockhash);
+    context.banks_client.process_transaction(transaction).await
+}
+
+/// Custom program error of a failed transaction.
+fn custom_error(err: TransportError) -> ProgramError {
+    match err {
+        TransportError::TransactionError(TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(code),
+        )) => ProgramError::Custom(code),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
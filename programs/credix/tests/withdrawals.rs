@@ -0,0 +1,112 @@
+//! The pro rata fill of withdrawal requests: closing an epoch and filling
+//! its requests from the escrows.
+
+use anchor_lang::prelude::ProgramError;
+use credix::errors::ErrorCode;
+use credix::withdrawals::{mul_div, EpochFill, RequestFill};
+
+#[test]
+fn mul_div_rounds_down() {
+    assert_eq!(mul_div(10, 2, 3).unwrap(), 6);
+    assert_eq!(mul_div(1, 1, 2).unwrap(), 0);
+    assert_eq!(mul_div(9, 1, 3).unwrap(), 3);
+    // Intermediate products beyond u64.
+    assert_eq!(mul_div(u64::MAX, u64::MAX, u64::MAX).unwrap(), u64::MAX);
+}
+
+#[test]
+fn mul_div_rejects_zero_divisors_and_overflows() {
+    let err: ProgramError = ErrorCode::ZeroDivision.into();
+    assert_eq!(mul_div(1, 1, 0).unwrap_err(), err);
+    let err: ProgramError = ErrorCode::Overflow.into();
+    assert_eq!(mul_div(u64::MAX, 2, 1).unwrap_err(), err);
+}
+
+#[test]
+fn liquid_pools_fill_every_request() {
+    // 300 of 1000 LP tokens of a pool worth 2000.
+    let fill = EpochFill::new(300, 2_000, 1_000, 10_000).unwrap();
+    assert_eq!(
+        fill,
+        EpochFill {
+            closed_lp: 300,
+            fill_lp: 300,
+            fill_base: 600,
+        }
+    );
+    assert_eq!(
+        fill.request_fill(100).unwrap(),
+        RequestFill {
+            lp_amount: 100,
+            base_amount: 200,
+            remaining_lp: 0,
+        }
+    );
+}
+
+#[test]
+fn illiquid_pools_fill_requests_pro_rata() {
+    // Worth 600, of which 150 are liquid.
+    let fill = EpochFill::new(300, 2_000, 1_000, 150).unwrap();
+    assert_eq!(fill.fill_base, 150);
+    assert_eq!(fill.fill_lp, 75);
+
+    let request = fill.request_fill(200).unwrap();
+    assert_eq!(
+        request,
+        RequestFill {
+            lp_amount: 50,
+            base_amount: 100,
+            remaining_lp: 150,
+        }
+    );
+}
+
+#[test]
+fn worthless_lp_tokens_are_burned_for_nothing() {
+    let fill = EpochFill::new(300, 0, 1_000, 150).unwrap();
+    assert_eq!(
+        fill,
+        EpochFill {
+            closed_lp: 300,
+            fill_lp: 300,
+            fill_base: 0,
+        }
+    );
+    let request = fill.request_fill(300).unwrap();
+    assert_eq!(request.lp_amount, 300);
+    assert_eq!(request.base_amount, 0);
+
+    // An empty pool has no LP tokens to value.
+    assert_eq!(EpochFill::new(0, 0, 0, 0).unwrap().fill_base, 0);
+}
+
+#[test]
+fn requests_never_fill_more_than_their_epoch() {
+    let requests = [7, 13, 101, 1, 58];
+    let closed_lp = requests.iter().sum();
+    let fill = EpochFill::new(closed_lp, 1_234_567, 999_983, 77_777).unwrap();
+
+    let mut burned = 0;
+    let mut paid = 0;
+    for lp_amount in requests {
+        let request = fill.request_fill(lp_amount).unwrap();
+        // The escrow keeps what isn't burned.
+        assert_eq!(request.lp_amount + request.remaining_lp, lp_amount);
+        burned += request.lp_amount;
+        paid += request.base_amount;
+    }
+    assert!(burned <= fill.fill_lp);
+    assert!(paid <= fill.fill_base);
+    // Rounding loses less than a token per request.
+    assert!(fill.fill_lp - burned < requests.len() as u64);
+    assert!(fill.fill_base - paid < requests.len() as u64);
+}
+
+#[test]
+fn empty_epochs_fill_nothing() {
+    let fill = EpochFill::new(0, 2_000, 1_000, 150).unwrap();
+    assert_eq!(fill, EpochFill::default());
+    let err: ProgramError = ErrorCode::ZeroDivision.into();
+    assert_eq!(fill.request_fill(1).unwrap_err(), err);
+}
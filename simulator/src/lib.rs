@@ -0,0 +1,263 @@
+//! Simulates requests to the credix permissioned market proxy off-chain,
+//! from snapshots of the accounts they touch.
+//!
+//! The middleware of the proxy runs as it does on-chain, then the
+//! instructions it invokes around the DEX instruction are replayed against
+//! the credix and token programs. A request is rejected by the first failing
+//! check: the credix pass and gateway token of the LP holder, frozen or
+//! missing token accounts, unauthorized owners or delegates, the price band
+//! or the referral. The DEX instruction itself is not executed.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{system_program, sysvar};
+use credix_permissioned_market::{ALLOWLIST_ACCOUNTS, CREDIX_ACCOUNTS};
+use serum_dex_permissioned::serum_dex::state::OpenOrders;
+use serum_dex_permissioned::{DryRun, Seeds};
+use std::collections::HashMap;
+use std::mem::size_of;
+
+mod runtime;
+
+// Accounts of the proxy preceding the open orders account being initialized:
+// the DEX program, the credix accounts, the allowlist and its entry, then the
+// DEX program and system program of `OpenOrdersPda`. The open orders account
+// comes first in the accounts of `InitOpenOrders`.
+const INIT_OPEN_ORDERS_INDEX: usize = 1 + CREDIX_ACCOUNTS + ALLOWLIST_ACCOUNTS + 2;
+// Padding serum puts around the state of its accounts.
+const SERUM_PADDING: usize = 12;
+
+/// State of an account, as returned by `getAccountInfo`.
+#[derive(Clone, Debug)]
+pub struct AccountSnapshot {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+}
+
+/// Step of a request at which it is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// The middleware of the proxy, before anything is invoked.
+    Middleware,
+    /// The pre-instruction at the given index of the plan.
+    PreInstruction(usize),
+    /// The post-instruction at the given index of the plan.
+    PostInstruction(usize),
+}
+
+/// First failing check of a request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub step: Step,
+    /// Program returning the error, the proxy for middleware errors.
+    pub program_id: Pubkey,
+    pub error: ProgramError,
+}
+
+/// Outcome of a simulated request.
+#[derive(Debug)]
+pub struct Simulation {
+    /// Instructions the proxy invokes, `None` if the middleware rejects the
+    /// request.
+    pub plan: Option<DryRun>,
+    pub rejection: Option<Rejection>,
+    /// Logs of the programs run, up to the rejection if any.
+    pub logs: Vec<String>,
+}
+
+impl Simulation {
+    pub fn is_ok(&self) -> bool {
+        self.rejection.is_none()
+    }
+}
+
+/// Snapshots of the accounts of the requests to simulate, with the clock
+/// they are simulated at.
+pub struct Simulator {
+    accounts: HashMap<Pubkey, AccountSnapshot>,
+    clock: Clock,
+}
+
+impl Simulator {
+    pub fn new(unix_timestamp: i64) -> Self {
+        Self {
+            accounts: HashMap::new(),
+            clock: Clock {
+                unix_timestamp,
+                ..Clock::default()
+            },
+        }
+    }
+
+    /// Adds the snapshot of an account. Accounts without a snapshot are
+    /// simulated as not existing.
+    pub fn account(mut self, key: Pubkey, account: AccountSnapshot) -> Self {
+        self.accounts.insert(key, account);
+        self
+    }
+
+    /// Simulates a request to the proxy. Simulations don't modify the
+    /// snapshots.
+    pub fn simulate(&self, ix: &Instruction) -> Simulation {
+        runtime::install(self.clock.clone());
+
+        let mut storage = self.storage(ix);
+        let mut infos: HashMap<Pubkey, AccountInfo> = HashMap::new();
+        for (key, account) in storage.iter_mut() {
+            let (is_signer, is_writable) = ix
+                .accounts
+                .iter()
+                .filter(|meta| &meta.pubkey == key)
+                .fold((false, false), |(signer, writable), meta| {
+                    (signer || meta.is_signer, writable || meta.is_writable)
+                });
+            infos.insert(
+                *key,
+                AccountInfo::new(
+                    key,
+                    is_signer,
+                    is_writable,
+                    &mut account.lamports,
+                    &mut account.data,
+                    &account.owner,
+                    account.executable,
+                    0,
+                ),
+            );
+        }
+        let accounts: Vec<AccountInfo> = ix
+            .accounts
+            .iter()
+            .map(|meta| infos[&meta.pubkey].clone())
+            .collect();
+
+        // The middleware creates the open orders accounts it initializes.
+        let dry_run = runtime::run(&ix.program_id, || {
+            credix_permissioned_market::dry_run(&ix.program_id, &accounts, &ix.data)
+        });
+        let (plan, rejection) = match dry_run {
+            Ok(Some(plan)) => {
+                let rejection = replay(&ix.program_id, &plan, &accounts);
+                (Some(plan), rejection)
+            }
+            // The proxy rejects instructions it doesn't handle.
+            Ok(None) => (
+                None,
+                Some(Rejection {
+                    step: Step::Middleware,
+                    program_id: ix.program_id,
+                    error: ProgramError::InvalidInstructionData,
+                }),
+            ),
+            Err(error) => (
+                None,
+                Some(Rejection {
+                    step: Step::Middleware,
+                    program_id: ix.program_id,
+                    error,
+                }),
+            ),
+        };
+
+        Simulation {
+            plan,
+            rejection,
+            logs: runtime::take_logs(),
+        }
+    }
+
+    // Copies the snapshots of the accounts of `ix`, filling in the ones
+    // missing the way the runtime sees them.
+    fn storage(&self, ix: &Instruction) -> HashMap<Pubkey, AccountSnapshot> {
+        let mut storage = HashMap::new();
+        for meta in &ix.accounts {
+            let account = self
+                .accounts
+                .get(&meta.pubkey)
+                .cloned()
+                .unwrap_or_else(|| missing_account(&meta.pubkey));
+            storage.insert(meta.pubkey, account);
+        }
+
+        // Open orders accounts are created by the proxy before it invokes
+        // the DEX, which the simulation doesn't do.
+        let is_init = matches!(ix.data.get(1), Some(0) | Some(2));
+        if let (true, Some(dex_program), Some(open_orders)) = (
+            is_init,
+            ix.accounts.first(),
+            ix.accounts.get(INIT_OPEN_ORDERS_INDEX),
+        ) {
+            if !self.accounts.contains_key(&open_orders.pubkey) {
+                let data = vec![0; size_of::<OpenOrders>() + SERUM_PADDING];
+                storage.insert(
+                    open_orders.pubkey,
+                    AccountSnapshot {
+                        lamports: Rent::default().minimum_balance(data.len()),
+                        data,
+                        owner: dex_program.pubkey,
+                        executable: false,
+                    },
+                );
+            }
+        }
+        storage
+    }
+}
+
+fn missing_account(key: &Pubkey) -> AccountSnapshot {
+    if key == &sysvar::rent::ID {
+        let data = bincode::serialize(&Rent::default()).unwrap();
+        return AccountSnapshot {
+            lamports: 1,
+            data,
+            owner: sysvar::ID,
+            executable: false,
+        };
+    }
+    AccountSnapshot {
+        lamports: 0,
+        data: Vec::new(),
+        owner: system_program::ID,
+        executable: false,
+    }
+}
+
+// Invokes the instructions of the plan around the DEX instruction, returning
+// the first one failing.
+fn replay(program_id: &Pubkey, plan: &DryRun, accounts: &[AccountInfo]) -> Option<Rejection> {
+    let pre = plan
+        .pre_instructions
+        .iter()
+        .enumerate()
+        .map(|(i, ix)| (Step::PreInstruction(i), ix));
+    let post = plan
+        .post_instructions
+        .iter()
+        .enumerate()
+        .map(|(i, ix)| (Step::PostInstruction(i), ix));
+    pre.chain(post).find_map(|(step, (ix, seeds))| {
+        invoke(program_id, ix, accounts, seeds)
+            .err()
+            .map(|error| Rejection {
+                step,
+                program_id: ix.program_id,
+                error,
+            })
+    })
+}
+
+fn invoke(
+    program_id: &Pubkey,
+    ix: &Instruction,
+    accounts: &[AccountInfo],
+    seeds: &Seeds,
+) -> ProgramResult {
+    let seeds: Vec<Vec<&[u8]>> = seeds
+        .iter()
+        .map(|seeds| seeds.iter().map(Vec::as_slice).collect())
+        .collect();
+    let seeds: Vec<&[&[u8]]> = seeds.iter().map(Vec::as_slice).collect();
+    runtime::invoke(program_id, ix, accounts, &seeds)
+}
@@ -0,0 +1,115 @@
+//! Native stand-in for the parts of the Solana runtime the proxy relies on:
+//! cross program invocations, the clock sysvar and program logs.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::SUCCESS;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use std::cell::RefCell;
+use std::sync::Once;
+
+thread_local! {
+    // Programs being executed, the innermost last.
+    static CALLERS: RefCell<Vec<Pubkey>> = const { RefCell::new(Vec::new()) };
+    static LOGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+}
+
+/// Installs the syscall stubs, once per process. The state they act on is
+/// kept per thread, so simulations may run concurrently.
+pub fn install(clock: Clock) {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        set_syscall_stubs(Box::new(Stubs));
+    });
+    CLOCK.with(|c| *c.borrow_mut() = clock);
+    LOGS.with(|logs| logs.borrow_mut().clear());
+}
+
+/// Takes the logs of the programs run since the last call.
+pub fn take_logs() -> Vec<String> {
+    LOGS.with(|logs| logs.take())
+}
+
+/// Runs `f` as the program `program_id`, which signs for its PDAs in the
+/// instructions it invokes.
+pub fn run<T>(program_id: &Pubkey, f: impl FnOnce() -> T) -> T {
+    CALLERS.with(|callers| callers.borrow_mut().push(*program_id));
+    let result = f();
+    CALLERS.with(|callers| callers.borrow_mut().pop());
+    result
+}
+
+/// Invokes `ix` on behalf of `caller`, which signs for the PDAs derived from
+/// `seeds`. Programs other than credix and the token program are skipped.
+pub fn invoke(
+    caller: &Pubkey,
+    ix: &Instruction,
+    account_infos: &[AccountInfo],
+    seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let signers = seeds
+        .iter()
+        .map(|seeds| Pubkey::create_program_address(seeds, caller))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+
+    let mut accounts = Vec::with_capacity(ix.accounts.len());
+    for meta in &ix.accounts {
+        let mut acc = account_infos
+            .iter()
+            .find(|acc| acc.key == &meta.pubkey)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?
+            .clone();
+        if meta.is_signer && !acc.is_signer && !signers.contains(&meta.pubkey) {
+            LOGS.with(|logs| {
+                logs.borrow_mut()
+                    .push(format!("{}'s signer privilege escalated", meta.pubkey))
+            });
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        acc.is_signer = meta.is_signer;
+        acc.is_writable = meta.is_writable;
+        accounts.push(acc);
+    }
+
+    run(&ix.program_id, || {
+        if ix.program_id == credix::ID {
+            credix::process_instruction(&ix.program_id, &accounts, &ix.data)
+        } else if ix.program_id == spl_token::ID {
+            spl_token::processor::Processor::process(&ix.program_id, &accounts, &ix.data)
+        } else {
+            Ok(())
+        }
+    })
+}
+
+struct Stubs;
+
+impl SyscallStubs for Stubs {
+    fn sol_log(&self, message: &str) {
+        LOGS.with(|logs| logs.borrow_mut().push(format!("Program log: {}", message)));
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = CALLERS
+            .with(|callers| callers.borrow().last().copied())
+            .ok_or(ProgramError::InvalidArgument)?;
+        invoke(&caller, instruction, account_infos, signers_seeds)
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        CLOCK.with(|clock| unsafe { *(var_addr as *mut Clock) = clock.borrow().clone() });
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+}
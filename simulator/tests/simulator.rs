@@ -0,0 +1,551 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::{bpf_loader, system_program};
+use anchor_spl::associated_token;
+use borsh::BorshSerialize;
+use credix::state::{CredixPass, GlobalMarketState};
+use credix_client::{
+    find_allowlist, find_allowlist_entry, find_credix_pass, find_permissioned_signing_authority,
+    find_signing_authority, find_trading_delegate, CredixMarket, MarketAccounts,
+    PermissionedMarket,
+};
+use credix_permissioned_market::{AllowlistEntry, MarketAllowlist};
+use credix_simulator::{AccountSnapshot, Simulator, Step};
+use serum_dex_permissioned::serum_dex::instruction::{
+    CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior,
+};
+use serum_dex_permissioned::serum_dex::matching::{OrderType, Side};
+use serum_dex_permissioned::testing::TestMarket;
+use serum_dex_permissioned::{TradingDelegate, CANCEL_ORDERS};
+use solana_gateway::state::GatewayToken;
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+use std::num::NonZeroU64;
+
+const NOW: i64 = 1_640_000_000;
+
+struct Fixture {
+    market: PermissionedMarket,
+    owner: Pubkey,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let credix = CredixMarket::new(
+            "credix-market",
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let accounts = MarketAccounts {
+            market: Pubkey::new_unique(),
+            request_queue: Pubkey::new_unique(),
+            event_queue: Pubkey::new_unique(),
+            bids: Pubkey::new_unique(),
+            asks: Pubkey::new_unique(),
+            coin_vault: Pubkey::new_unique(),
+            pc_vault: Pubkey::new_unique(),
+            vault_signer: Pubkey::new_unique(),
+        };
+        Self {
+            market: PermissionedMarket::new(credix, credix_permissioned_market::dex::ID, accounts),
+            owner: Pubkey::new_unique(),
+        }
+    }
+
+    /// Snapshots of the accounts of an LP holder with frozen LP tokens.
+    fn simulator(&self, pass_active: bool) -> Simulator {
+        self.simulator_with_pass(CredixPass {
+            active: pass_active,
+            ..CredixPass::default()
+        })
+    }
+
+    fn simulator_with_pass(&self, pass: CredixPass) -> Simulator {
+        self.simulator_with(pass, self.global_market_state())
+    }
+
+    fn global_market_state(&self) -> GlobalMarketState {
+        let credix = &self.market.credix;
+        let (_, signing_authority_bump) =
+            find_signing_authority(&credix.program_id, &credix.global_market_state);
+        GlobalMarketState {
+            gatekeeper_network: credix.gatekeeper_network,
+            liquidity_pool_token_mint_account: Pubkey::new_unique(),
+            lp_token_mint_account: credix.lp_token_mint,
+            signing_authority_bump,
+            bump: 0,
+            owner: Pubkey::new_unique(),
+            pool_value: 0,
+            price_band_bps: 0,
+            trading_halted: false,
+            ..GlobalMarketState::default()
+        }
+    }
+
+    fn simulator_with(
+        &self,
+        pass: CredixPass,
+        global_market_state: GlobalMarketState,
+    ) -> Simulator {
+        let credix = &self.market.credix;
+        let (credix_pass, credix_pass_bump) =
+            find_credix_pass(&credix.program_id, &credix.global_market_state, &self.owner);
+
+        let pass = CredixPass {
+            bump: credix_pass_bump,
+            ..pass
+        };
+        let mint = Mint {
+            supply: 1_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: Some(credix.signing_authority()).into(),
+            ..Mint::default()
+        };
+        let lp_tokens = TokenAccount {
+            mint: credix.lp_token_mint,
+            owner: self.owner,
+            amount: 1_000_000,
+            state: AccountState::Frozen,
+            ..TokenAccount::default()
+        };
+        let (gateway_token, gateway_token_snapshot) = self.gateway_token(None);
+
+        Simulator::new(NOW)
+            .account(self.owner, snapshot(system_program::ID, Vec::new()))
+            .account(
+                credix.lp_token_account(&self.owner),
+                snapshot(spl_token::ID, pack(lp_tokens)),
+            )
+            .account(
+                find_permissioned_signing_authority(&self.market.program_id).0,
+                snapshot(self.market.program_id, vec![0; 8]),
+            )
+            .account(credix.lp_token_mint, snapshot(spl_token::ID, pack(mint)))
+            .account(
+                credix.global_market_state,
+                snapshot(credix.program_id, serialize(global_market_state)),
+            )
+            .account(credix_pass, snapshot(credix.program_id, serialize(pass)))
+            .account(gateway_token, gateway_token_snapshot)
+            .account(spl_token::ID, program())
+            .account(credix.program_id, program())
+            .account(associated_token::ID, program())
+    }
+
+    /// Gateway token of the owner, expiring at the given time if any.
+    fn gateway_token(&self, expire_time: Option<i64>) -> (Pubkey, AccountSnapshot) {
+        let credix = &self.market.credix;
+        let gateway_token = GatewayToken::new_vanilla(
+            &self.owner,
+            &credix.gatekeeper_network,
+            &Pubkey::new_unique(),
+            &expire_time,
+        );
+        (
+            credix.gateway_token(&self.owner),
+            snapshot(
+                credix::gateway_program::ID,
+                gateway_token.try_to_vec().unwrap(),
+            ),
+        )
+    }
+
+    /// Snapshot of the serum market of the LP token.
+    fn dex_market(&self) -> AccountSnapshot {
+        let mut market = TestMarket::new(self.market.program_id, self.market.dex_program_id);
+        market.market = self.market.accounts.market;
+        market.coin_mint = self.market.credix.lp_token_mint;
+        let account = market.market_account();
+        snapshot(account.owner, account.data)
+    }
+
+    fn ask(&self) -> Instruction {
+        self.ask_at(1_000)
+    }
+
+    /// Ask of 10 coin lots at the given price, in pc lots per coin lot.
+    fn ask_at(&self, limit_price: u64) -> Instruction {
+        let lp_token_account = self.market.credix.lp_token_account(&self.owner);
+        self.market.new_order_v3(
+            &self.owner,
+            0,
+            &lp_token_account,
+            None,
+            NewOrderInstructionV3 {
+                side: Side::Ask,
+                limit_price: NonZeroU64::new(limit_price).unwrap(),
+                max_coin_qty: NonZeroU64::new(10).unwrap(),
+                max_native_pc_qty_including_fees: NonZeroU64::new(u64::MAX).unwrap(),
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                order_type: OrderType::Limit,
+                client_order_id: 1,
+                limit: 10,
+            },
+        )
+    }
+
+    fn cancel_order(&self) -> Instruction {
+        self.delegated_cancel_order(None)
+    }
+
+    fn delegated_cancel_order(&self, delegate: Option<&Pubkey>) -> Instruction {
+        self.market.cancel_order_v2(
+            &self.owner,
+            0,
+            delegate,
+            CancelOrderInstructionV2 {
+                side: Side::Ask,
+                order_id: 1,
+            },
+        )
+    }
+
+    /// Trading delegate record of the owner letting `delegate` cancel orders.
+    fn trading_delegate(&self, delegate: Pubkey, expiry: i64) -> (Pubkey, AccountSnapshot) {
+        let (key, bump) = find_trading_delegate(&self.market.program_id, &self.owner);
+        let trading_delegate = TradingDelegate {
+            owner: self.owner,
+            delegate,
+            permissions: CANCEL_ORDERS,
+            expiry,
+            bump,
+        };
+        (
+            key,
+            snapshot(self.market.program_id, serialize(trading_delegate)),
+        )
+    }
+}
+
+fn snapshot(owner: Pubkey, data: Vec<u8>) -> AccountSnapshot {
+    AccountSnapshot {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner,
+        executable: false,
+    }
+}
+
+fn program() -> AccountSnapshot {
+    AccountSnapshot {
+        executable: true,
+        ..snapshot(bpf_loader::ID, Vec::new())
+    }
+}
+
+fn pack<T: Pack>(state: T) -> Vec<u8> {
+    let mut data = vec![0; T::LEN];
+    state.pack_into_slice(&mut data);
+    data
+}
+
+fn serialize<T: AccountSerialize>(account: T) -> Vec<u8> {
+    let mut data = Vec::new();
+    account.try_serialize(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn cancel_order_thaws_and_freezes_lp_tokens() {
+    let fixture = Fixture::new();
+    let simulation = fixture.simulator(true).simulate(&fixture.cancel_order());
+
+    assert_eq!(simulation.rejection, None, "{:#?}", simulation.logs);
+    let plan = simulation.plan.unwrap();
+    assert_eq!(plan.instruction.program_id, fixture.market.dex_program_id);
+    assert_eq!(plan.pre_instructions.len(), 1);
+    assert_eq!(plan.pre_instructions[0].0.program_id, credix::ID);
+    assert_eq!(plan.post_instructions.len(), 1);
+    assert_eq!(plan.post_instructions[0].0.program_id, credix::ID);
+}
+
+#[test]
+fn init_open_orders_freezes_lp_tokens() {
+    let fixture = Fixture::new();
+    let ix = fixture.market.init_open_orders(&fixture.owner, 0);
+    let simulation = fixture
+        .simulator(true)
+        .account(fixture.market.accounts.market, fixture.dex_market())
+        .account(fixture.market.dex_program_id, program())
+        .account(system_program::ID, program())
+        .simulate(&ix);
+
+    assert_eq!(simulation.rejection, None, "{:#?}", simulation.logs);
+    let plan = simulation.plan.unwrap();
+    assert_eq!(plan.instruction.program_id, fixture.market.dex_program_id);
+    let open_orders = fixture.market.open_orders(&fixture.owner, 0);
+    assert_eq!(plan.instruction.accounts[0].pubkey, open_orders);
+    assert_eq!(plan.post_instructions.len(), 1);
+    assert_eq!(plan.post_instructions[0].0.program_id, credix::ID);
+}
+
+#[test]
+fn inactive_credix_pass_is_rejected_by_the_thaw() {
+    let fixture = Fixture::new();
+    let simulation = fixture.simulator(false).simulate(&fixture.cancel_order());
+
+    let rejection = simulation.rejection.unwrap();
+    assert_eq!(rejection.step, Step::PreInstruction(0));
+    assert_eq!(rejection.program_id, credix::ID);
+    assert_eq!(
+        rejection.error,
+        credix::errors::ErrorCode::CredixPassInactive.into()
+    );
+}
+
+#[test]
+fn gateway_token_expiry_is_not_checked_by_the_thaw() {
+    let fixture = Fixture::new();
+    // Credix checks the gatekeeper network of the token, not its expiry.
+    let (gateway_token, snapshot) = fixture.gateway_token(Some(NOW - 1));
+    let simulation = fixture
+        .simulator(true)
+        .account(gateway_token, snapshot)
+        .simulate(&fixture.cancel_order());
+
+    assert!(simulation.is_ok(), "{:#?}", simulation);
+}
+
+#[test]
+fn settlements_pay_the_referral_of_the_proxy() {
+    let fixture = Fixture::new();
+    let lp_token_account = fixture.market.credix.lp_token_account(&fixture.owner);
+    let (pc_wallet, referral_wallet) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let ix = fixture.market.settle_funds(
+        &fixture.owner,
+        0,
+        &lp_token_account,
+        &pc_wallet,
+        &referral_wallet,
+    );
+    let simulate = |referral| {
+        let referral_tokens = TokenAccount {
+            mint: fixture.market.credix.base_mint,
+            owner: referral,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        fixture
+            .simulator(true)
+            .account(
+                referral_wallet,
+                snapshot(spl_token::ID, pack(referral_tokens)),
+            )
+            .simulate(&ix)
+    };
+
+    let simulation = simulate(Pubkey::new_unique());
+    let rejection = simulation.rejection.unwrap();
+    assert_eq!(rejection.step, Step::Middleware);
+    assert_eq!(
+        rejection.error,
+        serum_dex_permissioned::ErrorCode::InvalidReferral.into()
+    );
+
+    let simulation = simulate(credix_permissioned_market::referral::ID);
+    assert!(simulation.is_ok(), "{:#?}", simulation);
+}
+
+#[test]
+fn missing_owner_signature_is_rejected_by_the_middleware() {
+    let fixture = Fixture::new();
+    let mut ix = fixture.cancel_order();
+    for meta in ix.accounts.iter_mut() {
+        meta.is_signer = false;
+    }
+    let simulation = fixture.simulator(true).simulate(&ix);
+
+    let rejection = simulation.rejection.unwrap();
+    assert_eq!(rejection.step, Step::Middleware);
+    assert_eq!(
+        rejection.error,
+        serum_dex_permissioned::ErrorCode::UnauthorizedUser.into()
+    );
+    assert!(simulation.plan.is_none());
+}
+
+#[test]
+fn asks_are_rejected_during_the_lockup() {
+    let fixture = Fixture::new();
+    let simulator = |locked_until| {
+        fixture
+            .simulator_with_pass(CredixPass {
+                active: true,
+                locked_until,
+                ..CredixPass::default()
+            })
+            .account(fixture.market.accounts.market, fixture.dex_market())
+    };
+
+    let simulation = simulator(NOW + 1).simulate(&fixture.ask());
+    let rejection = simulation.rejection.unwrap();
+    assert_eq!(rejection.step, Step::PreInstruction(0));
+    assert_eq!(rejection.program_id, credix::ID);
+    assert_eq!(
+        rejection.error,
+        credix::errors::ErrorCode::LpTokensLocked.into()
+    );
+
+    let simulation = simulator(NOW).simulate(&fixture.ask());
+    assert!(simulation.is_ok(), "{:#?}", simulation);
+}
+
+#[test]
+fn orders_are_priced_within_the_band() {
+    let fixture = Fixture::new();
+    let simulator = |trading_halted| {
+        // 10 pc tokens per LP token, 1000 pc lots per coin lot on the test
+        // market.
+        let global_market_state = GlobalMarketState {
+            pool_value: 10_000_000,
+            price_band_bps: 100,
+            trading_halted,
+            ..fixture.global_market_state()
+        };
+        let pass = CredixPass {
+            active: true,
+            ..CredixPass::default()
+        };
+        fixture
+            .simulator_with(pass, global_market_state)
+            .account(fixture.market.accounts.market, fixture.dex_market())
+    };
+
+    for limit_price in [990, 1_000, 1_010] {
+        let simulation = simulator(false).simulate(&fixture.ask_at(limit_price));
+        assert!(simulation.is_ok(), "{:#?}", simulation);
+    }
+
+    for limit_price in [989, 1_011] {
+        let simulation = simulator(false).simulate(&fixture.ask_at(limit_price));
+        let rejection = simulation.rejection.unwrap();
+        assert_eq!(rejection.step, Step::Middleware);
+        assert_eq!(
+            rejection.error,
+            serum_dex_permissioned::ErrorCode::PriceOutsideBand.into()
+        );
+    }
+
+    let simulation = simulator(true).simulate(&fixture.ask());
+    let rejection = simulation.rejection.unwrap();
+    assert_eq!(rejection.step, Step::Middleware);
+    assert_eq!(
+        rejection.error,
+        serum_dex_permissioned::ErrorCode::TradingHalted.into()
+    );
+}
+
+#[test]
+fn allowlisted_markets_take_orders_of_listed_users() {
+    let fixture = Fixture::new();
+    let (program_id, market) = (fixture.market.program_id, fixture.market.accounts.market);
+    let allowlist = MarketAllowlist {
+        market,
+        entries: 1,
+        ..MarketAllowlist::default()
+    };
+    let simulator = fixture
+        .simulator(true)
+        .account(market, fixture.dex_market())
+        .account(
+            find_allowlist(&program_id, &market).0,
+            snapshot(program_id, serialize(allowlist)),
+        );
+
+    let simulation = simulator.simulate(&fixture.ask());
+    let rejection = simulation.rejection.unwrap();
+    assert_eq!(rejection.step, Step::Middleware);
+    assert_eq!(
+        rejection.error,
+        credix_permissioned_market::ErrorCode::NotAllowlisted.into()
+    );
+
+    let allowlist_entry = AllowlistEntry {
+        market,
+        user: fixture.owner,
+        ..AllowlistEntry::default()
+    };
+    let simulation = simulator
+        .account(
+            find_allowlist_entry(&program_id, &market, &fixture.owner).0,
+            snapshot(program_id, serialize(allowlist_entry)),
+        )
+        .simulate(&fixture.ask());
+    assert!(simulation.is_ok(), "{:#?}", simulation);
+}
+
+#[test]
+fn delegated_cancel_thaws_with_the_trading_delegate() {
+    let fixture = Fixture::new();
+    let delegate = Pubkey::new_unique();
+    let (trading_delegate, record) = fixture.trading_delegate(delegate, NOW + 1);
+    let simulation = fixture
+        .simulator(true)
+        .account(trading_delegate, record)
+        .simulate(&fixture.delegated_cancel_order(Some(&delegate)));
+
+    assert!(simulation.is_ok(), "{:#?}", simulation);
+    let plan = simulation.plan.unwrap();
+    let thaw = &plan.pre_instructions[0].0;
+    assert_eq!(thaw.program_id, credix::ID);
+    assert_eq!(
+        thaw.data,
+        anchor_lang::InstructionData::data(&credix::instruction::ThawDelegatedLpTokens {
+            permission: CANCEL_ORDERS
+        })
+    );
+    let freeze = &plan.post_instructions[0].0;
+    assert_eq!(
+        freeze.data,
+        anchor_lang::InstructionData::data(&credix::instruction::FreezeDelegatedLpTokens {
+            permission: CANCEL_ORDERS
+        })
+    );
+    assert!(thaw
+        .accounts
+        .iter()
+        .any(|meta| meta.pubkey == delegate && meta.is_signer));
+    assert!(!thaw.accounts[1].is_signer);
+}
+
+#[test]
+fn expired_and_foreign_delegates_are_rejected() {
+    let fixture = Fixture::new();
+    let delegate = Pubkey::new_unique();
+    let simulate = |record_delegate, expiry| {
+        let (trading_delegate, record) = fixture.trading_delegate(record_delegate, expiry);
+        fixture
+            .simulator(true)
+            .account(trading_delegate, record)
+            .simulate(&fixture.delegated_cancel_order(Some(&delegate)))
+    };
+
+    for simulation in [simulate(delegate, NOW), simulate(Pubkey::new_unique(), 0)] {
+        let rejection = simulation.rejection.unwrap();
+        assert_eq!(rejection.step, Step::Middleware);
+        assert_eq!(
+            rejection.error,
+            serum_dex_permissioned::ErrorCode::UnauthorizedDelegate.into()
+        );
+    }
+}
+
+#[test]
+fn lp_holder_must_own_the_open_orders_account() {
+    let fixture = Fixture::new();
+    // The owner of the open orders account signs for the LP tokens of
+    // another holder.
+    let mut ix = fixture.cancel_order();
+    ix.accounts[1].pubkey = Pubkey::new_unique();
+    ix.accounts[1].is_signer = false;
+    let simulation = fixture.simulator(true).simulate(&ix);
+
+    let rejection = simulation.rejection.unwrap();
+    assert_eq!(rejection.step, Step::Middleware);
+    assert_eq!(
+        rejection.error,
+        credix_permissioned_market::ErrorCode::InvalidLpHolder.into()
+    );
+}
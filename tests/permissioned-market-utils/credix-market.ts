@@ -92,10 +92,10 @@ export class CredixPermissionedMarket {
     this.gatewayNetwork = gatewayNetwork;
   }
   initOpenOrders(ix) {
-    this.proxy(ix, 3);
+    this.proxy(ix, 3, 4);
   }
   newOrderV3(ix) {
-    this.proxy(ix, 7);
+    this.proxy(ix, 7, 0);
   }
   cancelOrderV2(ix) {
     this.proxy(ix, 4);
@@ -115,8 +115,25 @@ export class CredixPermissionedMarket {
   consumeEvents(ix) {}
   consumeEventsPermissioned(ix) {}
 
-  proxy(ix, inititorIndex) {
+  // Allowlist accounts are only passed along when the market index is given.
+  proxy(ix, inititorIndex, marketIndex?: number) {
     let initiator: PublicKey = ix.keys[inititorIndex].pubkey;
+    let allowlistKeys = [];
+    if (marketIndex !== undefined) {
+      const market: PublicKey = ix.keys[marketIndex].pubkey;
+      const [allowlist, _allowlistBump] = findProgramAddressSync(
+        [Buffer.from("allowlist"), market.toBuffer()],
+        this.programId
+      );
+      const [allowlistEntry, _allowlistEntryBump] = findProgramAddressSync(
+        [Buffer.from("allowlist"), market.toBuffer(), initiator.toBuffer()],
+        this.programId
+      );
+      allowlistKeys = [
+        { pubkey: allowlist, isWritable: false, isSigner: false },
+        { pubkey: allowlistEntry, isWritable: false, isSigner: false },
+      ];
+    }
     let [permissionedMarketPDA, permissionedBump] = findProgramAddressSync(
       [Buffer.from(anchor.utils.bytes.utf8.encode("signing-authority"))],
       this.programId
@@ -181,6 +198,7 @@ export class CredixPermissionedMarket {
       { pubkey: SystemProgram.programId, isWritable: false, isSigner: false },
       { pubkey: SYSVAR_RENT_PUBKEY, isWritable: false, isSigner: false },
       { pubkey: gateway_account[0], isWritable: false, isSigner: false },
+      ...allowlistKeys,
       ...ix.keys,
     ];
     ix.data = Buffer.concat([Buffer.from([permissionedBump]), ix.data]);